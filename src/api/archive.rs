@@ -0,0 +1,378 @@
+use super::{RhaiResult, RhaiError};
+use super::fs::{Filter, walk_entries, path_string};
+
+use std::{fs, io};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf, Component};
+
+use rhai::{Engine, Map, Module};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use tar::EntryType;
+use zip::{ZipArchive, ZipWriter, CompressionMethod};
+use zip::write::FileOptions;
+
+enum Format {
+	Tar,
+	TarGz,
+	Zip
+}
+
+impl Format {
+	fn from_path(path: &str) -> RhaiResult<Self> {
+		let lower = path.to_lowercase();
+		if lower.ends_with(".tar.gz") || lower.ends_with(".tgz") {
+			Ok(Self::TarGz)
+		} else if lower.ends_with(".tar") {
+			Ok(Self::Tar)
+		} else if lower.ends_with(".zip") {
+			Ok(Self::Zip)
+		} else {
+			Err(err!(
+				"archive: unknown format {:?}, expected .tar, .tar.gz or .zip",
+				path
+			))
+		}
+	}
+}
+
+fn archive_err(path: &str, e: impl std::fmt::Display) -> RhaiError {
+	err!("archive {:?}: {}", path, e)
+}
+
+fn create(archive: &str, dir: &str) -> RhaiResult<()> {
+	create_with(archive, dir, Map::new())
+}
+
+/// Creates an archive containing everything in `dir`, the format is
+/// chosen by the extension of `archive`.
+///
+/// `include` and `exclude` globs are matched against the path relative
+/// to `dir`, an excluded directory is skipped entirely.
+fn create_with(archive: &str, dir: &str, opts: Map) -> RhaiResult<()> {
+	paint_act!("create archive {:?} from {:?}", archive, dir);
+	let format = Format::from_path(archive)?;
+	let filter = Filter::from_opts(&opts)?;
+	dry_run!(Ok(()));
+
+	let root = Path::new(dir);
+	if !root.is_dir() {
+		return Err(err!("archive: {:?} is not a directory", dir))
+	}
+
+	let file = File::create(archive)
+		.map_err(|e| archive_err(archive, e))?;
+
+	// the archive might be inside of `dir`, it must not contain itself
+	let (canonical_archive, canonical_root) = Path::new(archive).canonicalize()
+		.and_then(|a| root.canonicalize().map(|r| (a, r)))
+		.map_err(|e| archive_err(archive, e))?;
+
+	let entries = walk_entries(root, filter)?
+		.into_iter()
+		.filter(|(rel, _)| canonical_root.join(rel) != canonical_archive)
+		.map(|(rel, is_dir)| Ok((path_string(&rel)?, rel, is_dir)))
+		.collect::<RhaiResult<Vec<_>>>()?;
+
+	let r = match format {
+		Format::Tar => write_tar(file, root, &entries)
+			.and_then(|mut f| f.flush()),
+		Format::TarGz => {
+			let enc = GzEncoder::new(file, Compression::default());
+			write_tar(enc, root, &entries)
+				.and_then(|enc| enc.finish())
+				.map(|_| ())
+		},
+		Format::Zip => write_zip(file, root, &entries)
+	};
+
+	r.map_err(|e| archive_err(archive, e))
+}
+
+type Entry = (String, PathBuf, bool);
+
+fn write_tar<W: Write>(w: W, root: &Path, entries: &[Entry]) -> io::Result<W> {
+	let mut builder = tar::Builder::new(w);
+	builder.follow_symlinks(false);
+
+	for (name, rel, is_dir) in entries {
+		let path = root.join(rel);
+		if *is_dir {
+			builder.append_dir(name, &path)?;
+		} else {
+			builder.append_path_with_name(&path, name)?;
+		}
+	}
+
+	builder.into_inner()
+}
+
+fn write_zip(file: File, root: &Path, entries: &[Entry]) -> io::Result<()> {
+	let mut zip = ZipWriter::new(file);
+
+	for (name, rel, is_dir) in entries {
+		let path = root.join(rel);
+		let meta = fs::symlink_metadata(&path)?;
+
+		let mut options = FileOptions::default()
+			.compression_method(CompressionMethod::Deflated)
+			.large_file(meta.len() >= u32::MAX as u64);
+		if let Some(mode) = mode(&meta) {
+			options = options.unix_permissions(mode);
+		}
+
+		if meta.file_type().is_symlink() {
+			let target = fs::read_link(&path)?;
+			zip.add_symlink(name, target.to_string_lossy(), options)?;
+		} else if *is_dir {
+			zip.add_directory(name, options)?;
+		} else {
+			zip.start_file(name, options)?;
+			io::copy(&mut File::open(&path)?, &mut zip)?;
+		}
+	}
+
+	zip.finish()?;
+	Ok(())
+}
+
+fn extract(archive: &str, dest: &str) -> RhaiResult<()> {
+	extract_with(archive, dest, Map::new())
+}
+
+/// Extracts an archive into `dest`, the format is chosen by the extension
+/// of `archive`.
+///
+/// `include` and `exclude` globs are matched against the paths in the
+/// archive. Entries which would end up outside of `dest`, symlinks
+/// pointing outside of it and writes through such symlinks are rejected.
+fn extract_with(archive: &str, dest: &str, opts: Map) -> RhaiResult<()> {
+	paint_act!("extract {:?} to {:?}", archive, dest);
+	let format = Format::from_path(archive)?;
+	let filter = Filter::from_opts(&opts)?;
+	dry_run!(Ok(()));
+
+	let dest = Path::new(dest);
+	let file = File::open(archive)
+		.and_then(|f| fs::create_dir_all(dest).map(|_| f))
+		.map_err(|e| archive_err(archive, e))?;
+
+	let r = match format {
+		Format::Tar => extract_tar(file, dest, &filter),
+		Format::TarGz => extract_tar(GzDecoder::new(file), dest, &filter),
+		Format::Zip => extract_zip(file, dest, &filter)
+	};
+
+	r.map_err(|e| archive_err(archive, e))
+}
+
+fn extract_tar<R: Read>(r: R, dest: &Path, filter: &Filter) -> io::Result<()> {
+	let mut archive = tar::Archive::new(r);
+	archive.set_preserve_permissions(true);
+	let canonical_dest = dest.canonicalize()?;
+
+	for entry in archive.entries()? {
+		let mut entry = entry?;
+		let path = normalize(&entry.path()?);
+		let kind = entry.header().entry_type();
+		let is_dir = kind.is_dir();
+		if path.as_os_str().is_empty() || !included(filter, &path, is_dir) {
+			continue
+		}
+
+		// a symlink is relative to its directory, a hard link to the root
+		let base = match kind {
+			EntryType::Symlink => path.parent(),
+			EntryType::Link => Some(Path::new("")),
+			_ => None
+		};
+		if let Some(base) = base {
+			let enclosed = entry.link_name()?
+				.map(|target| link_is_enclosed(base, &target))
+				.unwrap_or(false);
+			if !enclosed {
+				return Err(outside_err(&path))
+			}
+		}
+
+		// unpack_in checks this as well but with a less helpful error
+		let dir = if is_dir { Some(path.as_path()) } else { path.parent() };
+		if let Some(dir) = dir {
+			create_dirs_in(&canonical_dest, dest, dir)?;
+		}
+
+		if !entry.unpack_in(dest)? {
+			return Err(outside_err(&path))
+		}
+	}
+
+	Ok(())
+}
+
+fn extract_zip(file: File, dest: &Path, filter: &Filter) -> io::Result<()> {
+	let mut archive = ZipArchive::new(file)?;
+	let canonical_dest = dest.canonicalize()?;
+
+	for i in 0..archive.len() {
+		let mut entry = archive.by_index(i)?;
+		let path = match entry.enclosed_name() {
+			Some(p) => normalize(p),
+			None => return Err(outside_err(Path::new(entry.name())))
+		};
+		let is_dir = entry.is_dir();
+		if path.as_os_str().is_empty() || !included(filter, &path, is_dir) {
+			continue
+		}
+
+		let out = dest.join(&path);
+		if is_dir {
+			create_dirs_in(&canonical_dest, dest, &path)?;
+			continue
+		}
+
+		if let Some(parent) = path.parent() {
+			create_dirs_in(&canonical_dest, dest, parent)?;
+		}
+
+		// a previous entry or extraction might have left a symlink
+		if fs::symlink_metadata(&out).map(|m| m.is_symlink()).unwrap_or(false) {
+			fs::remove_file(&out)?;
+		}
+
+		let mode = entry.unix_mode();
+		if mode.map(|m| m & 0o170000 == 0o120000).unwrap_or(false) {
+			let mut target = String::new();
+			entry.read_to_string(&mut target)?;
+			let base = path.parent().unwrap_or(Path::new(""));
+			if !link_is_enclosed(base, Path::new(&target)) {
+				return Err(outside_err(&path))
+			}
+			symlink(Path::new(&target), &out)?;
+			continue
+		}
+
+		io::copy(&mut entry, &mut File::create(&out)?)?;
+		if let Some(mode) = mode {
+			set_mode(&out, mode)?;
+		}
+	}
+
+	Ok(())
+}
+
+/// Creates every directory of `rel` below `dest`, one at a time so that
+/// nothing gets written through a symlink pointing outside of `dest`.
+fn create_dirs_in(
+	canonical_dest: &Path,
+	dest: &Path,
+	rel: &Path
+) -> io::Result<()> {
+	let mut dir = dest.to_path_buf();
+	for comp in rel.components() {
+		dir.push(comp);
+		if fs::symlink_metadata(&dir).is_err() {
+			fs::create_dir(&dir)?;
+		}
+
+		if !dir.canonicalize()?.starts_with(canonical_dest) {
+			return Err(outside_err(rel))
+		}
+	}
+
+	Ok(())
+}
+
+/// Returns true if `target`, relative to the directory `base`, points to
+/// a path inside of the destination.
+fn link_is_enclosed(base: &Path, target: &Path) -> bool {
+	let mut depth = base.components().count();
+	for comp in target.components() {
+		match comp {
+			Component::Normal(_) => depth += 1,
+			Component::CurDir => {},
+			Component::ParentDir => match depth.checked_sub(1) {
+				Some(d) => depth = d,
+				None => return false
+			},
+			Component::RootDir | Component::Prefix(_) => return false
+		}
+	}
+
+	true
+}
+
+/// Returns true if no parent directory is excluded and the path matches
+/// the filter. With include globs directories are only created for the
+/// files in them.
+fn included(filter: &Filter, path: &Path, is_dir: bool) -> bool {
+	let excluded = path.ancestors()
+		.filter(|a| !a.as_os_str().is_empty())
+		.any(|a| filter.is_excluded(a));
+	if excluded {
+		false
+	} else if is_dir {
+		!filter.has_include()
+	} else {
+		filter.matches(path)
+	}
+}
+
+/// Removes `./` components.
+fn normalize(path: &Path) -> PathBuf {
+	path.components()
+		.filter(|c| !matches!(c, Component::CurDir))
+		.collect()
+}
+
+fn outside_err(path: &Path) -> io::Error {
+	io::Error::new(
+		io::ErrorKind::InvalidData,
+		format!("{:?} would be extracted outside of the destination", path)
+	)
+}
+
+#[cfg(unix)]
+fn mode(meta: &fs::Metadata) -> Option<u32> {
+	use std::os::unix::fs::PermissionsExt;
+
+	Some(meta.permissions().mode() & 0o7777)
+}
+
+#[cfg(not(unix))]
+fn mode(_meta: &fs::Metadata) -> Option<u32> {
+	None
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> io::Result<()> {
+	use std::os::unix::fs::PermissionsExt;
+
+	fs::set_permissions(path, fs::Permissions::from_mode(mode & 0o7777))
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: u32) -> io::Result<()> {
+	Ok(())
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+	std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+	std::os::windows::fs::symlink_file(target, link)
+}
+
+pub fn add(engine: &mut Engine) {
+	let mut archive_mod = Module::new();
+	archive_mod.set_native_fn("create", create);
+	archive_mod.set_native_fn("create", create_with);
+	archive_mod.set_native_fn("extract", extract);
+	archive_mod.set_native_fn("extract", extract_with);
+	engine
+		.register_static_module("archive", archive_mod.into());
+}
@@ -1,19 +1,43 @@
+use super::{RhaiResult, RhaiError, Retry, io_err, fs};
 
-use super::{RhaiResult, io_err};
+use std::{iter, mem};
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, Read, Write, BufRead, BufReader};
+use std::process::{Command, Stdio, Child};
+use std::sync::{mpsc, Arc, Mutex, Once};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
 
-use std::process::{Command, Stdio};
-use std::sync::mpsc;
-use std::thread;
+use rhai::{Array, Dynamic, Engine, Map, Module, FnPtr, NativeCallContext};
 
-use rhai::{Array, Engine, Module};
 
+#[derive(Debug, Clone)]
+enum Input {
+	Str(String),
+	File(String)
+}
 
 #[derive(Debug, Clone)]
 struct Cmd {
 	args: Vec<String>,
-	envs: Vec<(String, String)>,
+	/// none removes the variable
+	envs: Vec<(String, Option<String>)>,
 	env_clear: bool,
-	dir: Option<String>
+	dir: Option<String>,
+	stdin: Option<Input>,
+	/// commands which receive the stdout of the previous command
+	pipe: Vec<Cmd>,
+	/// in milliseconds
+	timeout: Option<u64>,
+	/// start the processes in a new process group
+	group: bool,
+	/// used as prefix for the output in execute_parallel
+	name: Option<String>,
+	retry: Option<Retry>
 }
 
 impl Cmd {
@@ -22,7 +46,13 @@ impl Cmd {
 			args: vec![s.into()],
 			envs: vec![],
 			env_clear: false,
-			dir: None
+			dir: None,
+			stdin: None,
+			pipe: vec![],
+			timeout: None,
+			group: false,
+			name: None,
+			retry: None
 		}
 	}
 
@@ -36,10 +66,30 @@ impl Cmd {
 			args,
 			envs: vec![],
 			env_clear: false,
-			dir: None
+			dir: None,
+			stdin: None,
+			pipe: vec![],
+			timeout: None,
+			group: false,
+			name: None,
+			retry: None
 		})
 	}
 
+	/// Splits `s` into arguments like a POSIX shell would, without
+	/// invoking a shell.
+	pub fn from_shell(s: &str) -> RhaiResult<Self> {
+		let args = shell_words::split(s)
+			.map_err(|e| err!("sh: could not parse {:?}: {}", s, e))?;
+		if args.is_empty() {
+			return Err(err!("sh: empty command"))
+		}
+
+		let mut cmd = Self::new(&args[0]);
+		cmd.args.extend(args.into_iter().skip(1));
+		Ok(cmd)
+	}
+
 	pub fn arg(&mut self, arg: &str) {
 		self.args.push(arg.into());
 	}
@@ -55,22 +105,94 @@ impl Cmd {
 		self.dir = Some(s.into());
 	}
 
-	/// if val === 0 the env is removed
+	/// Sets `key` to `val`, an empty `val` sets an empty variable. Use
+	/// `env_remove` to remove one.
 	pub fn env(&mut self, key: &str, val: &str) {
-		self.envs.push((key.into(), val.into()));
+		self.envs.push((key.into(), Some(val.into())));
+	}
+
+	/// Removes `key` from the environment the command inherits.
+	pub fn env_remove(&mut self, key: &str) {
+		self.envs.push((key.into(), None));
 	}
 
 	pub fn env_clear(&mut self) {
 		self.env_clear = true;
 	}
 
-	fn create_cmd(&mut self) -> RhaiResult<Command> {
+	/// Writes `s` to the stdin of the command.
+	pub fn stdin(&mut self, s: &str) {
+		self.stdin = Some(Input::Str(s.into()));
+	}
+
+	/// Uses the file at `path` as stdin of the command.
+	pub fn stdin_file(&mut self, path: &str) {
+		self.stdin = Some(Input::File(path.into()));
+	}
+
+	/// Connects the stdout of this command to the stdin of `other`.
+	pub fn pipe(&mut self, mut other: Cmd) {
+		let rest = mem::take(&mut other.pipe);
+		self.pipe.push(other);
+		self.pipe.extend(rest);
+	}
+
+	/// Kills the command and all processes it started if it runs longer
+	/// than `ms` milliseconds.
+	///
+	/// On unix the command is started in a new process group, so it
+	/// cannot read from the terminal.
+	pub fn timeout(&mut self, ms: i64) -> RhaiResult<()> {
+		if ms < 0 {
+			return Err(err!("timeout cannot be negative"))
+		}
+
+		self.timeout = Some(ms as u64);
+		self.group = true;
+		Ok(())
+	}
+
+	/// Retries the command up to `times` times if it fails, waiting
+	/// `delay_ms` before the first retry and doubling the delay after
+	/// every attempt.
+	///
+	/// Applies to `execute`, `output` and `run`.
+	pub fn retry(&mut self, times: i64, delay_ms: i64) -> RhaiResult<()> {
+		self.retry = Some(Retry::new(times, delay_ms)?);
+		Ok(())
+	}
+
+	/// Sets the name which prefixes the output in execute_parallel.
+	pub fn name(&mut self, name: &str) {
+		self.name = Some(name.into());
+	}
+
+	fn tag(&self) -> &str {
+		self.name.as_deref().unwrap_or(&self.args[0])
+	}
+
+	fn stages(&self) -> impl Iterator<Item=&Cmd> {
+		iter::once(self).chain(&self.pipe)
+	}
+
+	/// Returns the command line quoted so it can be pasted into a shell.
+	fn cmd_str(&self) -> String {
+		self.stages()
+			.map(|c| shell_words::join(&c.args))
+			.collect::<Vec<_>>()
+			.join(" | ")
+	}
+
+	fn create_cmd(&self) -> RhaiResult<Command> {
 		let mut cmd = Command::new(&self.args[0]);
 		cmd.args(self.args.iter().skip(1));
 
 		if let Some(dir) = &self.dir {
 			let abs_dir = dunce::canonicalize(dir)
-				.map_err(io_err)?;
+				.map_err(|e| err!(
+					"invalid directory {:?} for {:?}: {}",
+					dir, self.args[0], e
+				))?;
 			cmd.current_dir(abs_dir);
 		}
 
@@ -80,110 +202,1007 @@ impl Cmd {
 		}
 
 		for (key, val) in &self.envs {
-			if val.is_empty() {
-				cmd.env_remove(key);
+			match val {
+				Some(val) => cmd.env(key, val),
+				None => cmd.env_remove(key)
+			};
+		}
+
+		Ok(cmd)
+	}
+
+	/// Spawns every stage of the pipeline.
+	///
+	/// `stdout` is used for the last stage, stderr is only piped if
+	/// `capture_stderr` is true.
+	fn spawn(
+		&self,
+		stdout: Stdio,
+		capture_stderr: bool
+	) -> RhaiResult<Running> {
+		let stages: Vec<_> = self.stages().collect();
+		let mut running = Running {
+			children: Vec::with_capacity(stages.len()),
+			stdin: None,
+			watchdog: None,
+			group: self.group
+		};
+		let mut stdout = Some(stdout);
+		let mut stdin_str = None;
+
+		// fail before anything was started
+		let cmds = stages.iter()
+			.map(|stage| stage.create_cmd())
+			.collect::<RhaiResult<Vec<_>>>()?;
+
+		for (i, (stage, mut cmd)) in stages.iter().zip(cmds).enumerate() {
+
+			let prev_out = running.children.last_mut()
+				.and_then(|c| c.stdout.take());
+			match (prev_out, &self.stdin) {
+				(Some(out), _) => {
+					cmd.stdin(out);
+				},
+				(None, Some(Input::Str(s))) => {
+					cmd.stdin(Stdio::piped());
+					stdin_str = Some(s.clone());
+				},
+				(None, Some(Input::File(path))) => {
+					let file = File::open(path)
+						.map_err(|e| err!("could not open {} error {:?}", path, e))?;
+					cmd.stdin(file);
+				},
+				// a process outside of the foreground group gets stopped
+				// when it reads from the terminal
+				(None, None) if self.group => {
+					cmd.stdin(Stdio::null());
+				},
+				(None, None) => {}
+			}
+
+			if i + 1 < stages.len() {
+				cmd.stdout(Stdio::piped());
 			} else {
-				cmd.env(key, val);
+				cmd.stdout(stdout.take().unwrap());
 			}
+
+			if capture_stderr {
+				cmd.stderr(Stdio::piped());
+			}
+
+			if self.group {
+				new_process_group(&mut cmd);
+			}
+
+			let child = cmd.spawn()
+				.map_err(|e| {
+					running.kill();
+					spawn_err(&stage.args[0], e)
+				})?;
+			if self.group {
+				register_group(child.id());
+			}
+			running.children.push(child);
 		}
 
-		Ok(cmd)
+		if let Some(ms) = self.timeout {
+			let pids = running.children.iter().map(|c| c.id()).collect();
+			running.watchdog = Some(Watchdog::start(pids, ms));
+		}
+
+		if let Some(s) = stdin_str {
+			let mut w = running.children[0].stdin.take().unwrap();
+			running.stdin = Some(thread::spawn(move || {
+				// the process might exit without reading everything
+				let _ = w.write_all(s.as_bytes());
+			}));
+		}
+
+		Ok(running)
+	}
+
+	/// Spawns the pipeline and waits until it finished, retrying if
+	/// configured.
+	fn complete(
+		&self,
+		stdout: fn() -> Stdio,
+		capture_stderr: bool
+	) -> RhaiResult<Finished> {
+		let retry = self.retry.unwrap_or_default();
+		retry.run(
+			&format!("execution {:?}", self.cmd_str()),
+			|| {
+				let finished = self.spawn(stdout(), capture_stderr)?
+					.finish()?;
+				self.check_timeout(finished)
+			},
+			Finished::success
+		)
+	}
+
+	fn check_timeout(&self, finished: Finished) -> RhaiResult<Finished> {
+		if finished.timed_out {
+			let cmd_str = self.cmd_str();
+			paint_err!("execution {:?} timed out", cmd_str);
+			return Err(err!(
+				"timeout: execution {:?} exceeded {}ms",
+				cmd_str,
+				self.timeout.unwrap_or(0)
+			))
+		}
+
+		Ok(finished)
 	}
 
 	pub fn execute(&mut self) -> RhaiResult<()> {
-		let cmd_str = self.args.join(" ");
+		let cmd_str = self.cmd_str();
 		if let Some(dir) = &self.dir {
 			paint_act!("executing {:?} in {:?}", cmd_str, dir);
 		} else {
 			paint_act!("executing {:?}", cmd_str);
 		}
+		dry_run!(Ok(()));
 
-		let mut cmd = self.create_cmd()?;
+		let finished = self.complete(Stdio::inherit, false)?;
 
-		let status = cmd.status()
-			.expect("failed to execute");
-
-		if status.success() {
+		if finished.success() {
 			paint_ok!("execution {:?} successful", cmd_str);
 			Ok(())
 		} else {
 			paint_err!("execution {:?} failed", cmd_str);
-			Err(err!("execution {:?} failed", self))
+			Err(err!("execution {:?} failed {:?}", self, finished.codes))
 		}
 	}
 
 	pub fn output(&mut self) -> RhaiResult<String> {
-		let cmd_str = self.args.join(" ");
+		let cmd_str = self.cmd_str();
 		paint_act!("executing {:?}", cmd_str);
+		dry_run!(Ok(String::new()));
 
-		let mut cmd = self.create_cmd()?;
-		cmd.stdin(Stdio::inherit());
-		cmd.stderr(Stdio::inherit());
-
-		let output = cmd.output()
-			.expect("failed to execute");
+		let mut finished = self.complete(Stdio::piped, false)?;
 
-		let out_str = String::from_utf8(output.stdout)
-			.expect("output is not valid utf8");
+		let out_str = String::from_utf8(mem::take(&mut finished.stdout))
+			.map_err(|_| err!("output of {:?} is not valid utf8", cmd_str))?;
 
-		if output.status.success() {
+		if finished.success() {
 			paint_ok!("execution {:?} successful", cmd_str);
 			Ok(out_str)
 		} else {
 			paint_err!("execution {:?} failed", cmd_str);
-			Err(err!("execution {:?} failed", self))
+			Err(err!("execution {:?} failed {:?}", self, finished.codes))
+		}
+	}
+
+	/// Returns a map with `stdout`, `stderr`, `code`, `codes` and `success`.
+	///
+	/// Does not fail if the command exits with a non-zero status.
+	/// `code` is -1 if the process was terminated by a signal.
+	/// For a pipeline `code` is the code of the last command, `codes`
+	/// contains the code of every command and `success` is only true if
+	/// every command succeeded.
+	pub fn run(&mut self) -> RhaiResult<Map> {
+		let cmd_str = self.cmd_str();
+		paint_act!("executing {:?}", cmd_str);
+		dry_run!(Ok(Finished {
+			stdout: vec![],
+			stderr: vec![],
+			codes: self.stages().map(|_| Some(0)).collect(),
+			timed_out: false
+		}.into_map()));
+
+		let finished = self.complete(Stdio::piped, true)?;
+
+		if finished.success() {
+			paint_ok!("execution {:?} successful", cmd_str);
+		} else {
+			paint_err!("execution {:?} failed", cmd_str);
+		}
+
+		Ok(finished.into_map())
+	}
+
+	/// Calls `f(line, stream)` for every line the command writes to stdout
+	/// or stderr, where stream is either `"stdout"` or `"stderr"`.
+	/// If `f` returns `false` the command gets killed. stdin is empty
+	/// unless it was set.
+	///
+	/// Returns the same map as `run` with an additional `aborted` field.
+	pub fn stream(
+		ctx: NativeCallContext,
+		me: &mut Self,
+		f: FnPtr
+	) -> RhaiResult<Map> {
+		let cmd_str = me.cmd_str();
+		paint_act!("executing {:?}", cmd_str);
+		dry_run!(Ok({
+			let mut map = Finished {
+				stdout: vec![],
+				stderr: vec![],
+				codes: me.stages().map(|_| Some(0)).collect(),
+				timed_out: false
+			}.into_map();
+			map.insert("aborted".into(), false.into());
+			map
+		}));
+
+		let mut stdout = vec![];
+		let mut stderr = vec![];
+		let mut error = None;
+		let mut aborted = false;
+
+		// aborting should also kill everything the command started
+		let mut cmd = me.clone();
+		cmd.group = true;
+		let running = cmd.spawn(Stdio::piped(), true)?;
+		let finished = running.finish_lines(|stream, line| {
+			let (name, buf) = match stream {
+				Stream::Stdout => ("stdout", &mut stdout),
+				Stream::Stderr => ("stderr", &mut stderr)
+			};
+			buf.extend_from_slice(line.as_bytes());
+			buf.push(b'\n');
+
+			let r = f.call_within_context::<Dynamic>(
+				&ctx,
+				(line.to_string(), name.to_string())
+			);
+			match r {
+				Ok(r) if r.as_bool() == Ok(false) => {
+					aborted = true;
+					false
+				},
+				Ok(_) => true,
+				Err(e) => {
+					error = Some(e);
+					false
+				}
+			}
+		})?;
+
+		if let Some(e) = error {
+			return Err(e)
+		}
+
+		let mut finished = me.check_timeout(finished)?;
+		if aborted {
+			paint_act!("execution {:?} aborted", cmd_str);
+		} else if finished.success() {
+			paint_ok!("execution {:?} successful", cmd_str);
+		} else {
+			paint_err!("execution {:?} failed", cmd_str);
 		}
+
+		finished.stdout = stdout;
+		finished.stderr = stderr;
+		let mut map = finished.into_map();
+		map.insert("aborted".into(), aborted.into());
+		Ok(map)
 	}
 
-	pub fn execute_parallel(cmds_input: Array) -> RhaiResult<()> {
-		let mut cmds = Vec::with_capacity(cmds_input.len());
+	/// Starts the command in the background and returns a handle to it.
+	/// Registered as `start` since `spawn` is a reserved keyword in rhai.
+	///
+	/// stdout and stderr are collected and can be retrieved with `wait`
+	/// or `output`, stdin is empty unless it was set. A process which is
+	/// still running when the handle gets dropped is killed.
+	pub fn start(&mut self) -> RhaiResult<Process> {
+		let cmd_str = self.cmd_str();
+		paint_act!("starting {:?}", cmd_str);
+		dry_run!(Ok(Process::finished(self.clone(), Finished {
+			stdout: vec![],
+			stderr: vec![],
+			codes: self.stages().map(|_| Some(0)).collect(),
+			timed_out: false
+		})));
+
+		// so kill also reaches everything the command started
+		let mut cmd = self.clone();
+		cmd.group = true;
+		let mut running = cmd.spawn(Stdio::piped(), true)?;
+
+		let pid = running.children.first()
+			.map(|c| c.id())
+			.unwrap_or(0);
+		let stderrs = running.children.iter_mut()
+			.filter_map(|c| c.stderr.take())
+			.map(read_in_thread)
+			.collect();
+		let stdout = running.children.last_mut()
+			.and_then(|c| c.stdout.take())
+			.map(read_in_thread);
+
+		Ok(Process {
+			inner: Rc::new(RefCell::new(ProcessInner {
+				cmd: self.clone(),
+				pid,
+				running: Some(running),
+				stdout,
+				stderrs,
+				finished: None,
+				killed: false
+			}))
+		})
+	}
+
+	/// Executes all commands in parallel and fails if any command failed.
+	pub fn execute_parallel(cmds: Array) -> RhaiResult<()> {
+		let results = Self::execute_parallel_with(cmds, Map::new())?;
+
+		let failed: Vec<_> = results.into_iter()
+			.filter_map(|r| r.try_cast::<Map>())
+			.filter(|r| !matches!(r.get("success").map(|s| s.as_bool()), Some(Ok(true))))
+			.filter_map(|r| r.get("name").map(|n| n.to_string()))
+			.collect();
 
-		for cmd in cmds_input {
-			let cmd: Self = cmd.try_cast()
+		if failed.is_empty() {
+			Ok(())
+		} else {
+			Err(err!("parallel execution failed: {}", failed.join(", ")))
+		}
+	}
+
+	/// Executes all commands in parallel, prefixing every output line
+	/// with the name of the command.
+	///
+	/// ## Options
+	/// - `limit`: how many commands run at the same time (default all)
+	/// - `fail_fast`: kill the remaining commands once a command fails
+	///   (default true)
+	///
+	/// Returns a map with `name`, `code`, `success`, `timed_out` and
+	/// `skipped` for every command. stdin is empty unless it was set.
+	pub fn execute_parallel_with(
+		cmds_input: Array,
+		opts: Map
+	) -> RhaiResult<Array> {
+		let mut cmds = VecDeque::with_capacity(cmds_input.len());
+
+		for (i, cmd) in cmds_input.into_iter().enumerate() {
+			let mut cmd: Self = cmd.try_cast()
 				.ok_or(err!("only command struct allowed"))?;
-			cmds.push(cmd);
+			// so we can kill the command and everything it started
+			cmd.group = true;
+			cmds.push_back((i, cmd));
 		}
 
+		let count = cmds.len();
+		let limit = match opts.get("limit") {
+			Some(l) => l.as_int()
+				.map_err(|t| err!("limit expected int found {}", t))?
+				.max(1) as usize,
+			None => count
+		};
+		let fail_fast = match opts.get("fail_fast") {
+			Some(f) => f.as_bool()
+				.map_err(|t| err!("fail_fast expected bool found {}", t))?,
+			None => true
+		};
+
+		let names: Vec<String> = cmds.iter()
+			.map(|(_, c)| c.tag().to_string())
+			.collect();
+		let queue = Arc::new(Mutex::new(cmds));
+		let state = Arc::new(Parallel::default());
 		let (sender, recv) = mpsc::channel();
 
-		let cmds_count = cmds.len();
-		for mut cmd in cmds {
+		for _ in 0..limit.min(count) {
+			let queue = queue.clone();
+			let state = state.clone();
 			let sender = sender.clone();
-			// spawn a thread watcher
-			thread::spawn(move || {
-				// spawn command
-				let handle = thread::spawn(move || {
-					cmd.execute()
-						.expect("cmd failed");
-				});
-				sender.send(handle.join()).expect("sending failed");
+
+			thread::spawn(move || loop {
+				if state.stop.load(Ordering::SeqCst) {
+					break
+				}
+
+				let (i, cmd) = match queue.lock().unwrap().pop_front() {
+					Some(c) => c,
+					None => break
+				};
+
+				let r = cmd.execute_tagged(&state)
+					// rhai errors cannot be sent between threads
+					.map_err(|e| e.to_string());
+				if sender.send((i, r)).is_err() {
+					break
+				}
 			});
 		}
+		drop(sender);
 
-		for _ in 0..cmds_count {
-			let r = recv.recv().expect("failed to receive message from threads");
-			if let Err(e) = r {
-				return Err(err!("Some command failed {:?}", e))
+		let mut results: Vec<Option<Result<Finished, String>>> =
+			(0..count).map(|_| None).collect();
+		for (i, r) in recv {
+			let failed = !matches!(&r, Ok(f) if f.success() && !f.timed_out);
+			if let Err(e) = &r {
+				paint_err!("[{}] {}", names[i], e);
+			}
+			results[i] = Some(r);
+
+			if failed && fail_fast {
+				state.stop();
 			}
 		}
 
+		let results = results.into_iter()
+			.zip(names)
+			.map(|(r, name)| {
+				let mut map = Map::new();
+				map.insert("name".into(), name.into());
+				let (code, success, timed_out) = match &r {
+					Some(Ok(f)) => (
+						f.codes.last().copied().flatten().map(i64::from).unwrap_or(-1),
+						f.success() && !f.timed_out,
+						f.timed_out
+					),
+					_ => (-1, false, false)
+				};
+				map.insert("code".into(), code.into());
+				map.insert("success".into(), success.into());
+				map.insert("timed_out".into(), timed_out.into());
+				map.insert("skipped".into(), r.is_none().into());
+				Dynamic::from_map(map)
+			})
+			.collect();
+
+		Ok(results)
+	}
+
+	/// Executes the command prefixing every output line with its tag.
+	fn execute_tagged(&self, state: &Parallel) -> RhaiResult<Finished> {
+		let tag = self.tag();
+		paint_act!("[{}] executing {:?}", tag, self.cmd_str());
+		dry_run!(Ok(Finished {
+			stdout: vec![],
+			stderr: vec![],
+			codes: self.stages().map(|_| Some(0)).collect(),
+			timed_out: false
+		}));
+
+		let running = self.spawn(Stdio::piped(), true)?;
+		let own: Vec<_> = running.children.iter().map(|c| c.id()).collect();
+		state.register(&own);
+
+		let finished = running.finish_lines(|stream, line| {
+			match stream {
+				Stream::Stdout => println!("[{}] {}", tag, line),
+				Stream::Stderr => eprintln!("[{}] {}", tag, line)
+			}
+			true
+		});
+
+		state.unregister(&own);
+		let finished = finished?;
+
+		if finished.success() && !finished.timed_out {
+			paint_ok!("[{}] execution successful", tag);
+		} else if finished.timed_out {
+			paint_err!("[{}] execution timed out", tag);
+		} else {
+			paint_err!("[{}] execution failed", tag);
+		}
+
+		Ok(finished)
+	}
+}
+
+/// State shared between the threads of execute_parallel.
+#[derive(Default)]
+struct Parallel {
+	stop: AtomicBool,
+	/// the process groups which are currently running
+	pids: Mutex<Vec<u32>>
+}
+
+impl Parallel {
+	fn register(&self, pids: &[u32]) {
+		let mut running = self.pids.lock().unwrap();
+		// stop was called while we were spawning
+		if self.stop.load(Ordering::SeqCst) {
+			pids.iter().for_each(|p| kill_group(*p));
+		}
+		running.extend(pids);
+	}
+
+	fn unregister(&self, pids: &[u32]) {
+		self.pids.lock().unwrap().retain(|p| !pids.contains(p));
+	}
+
+	/// Kills every running process and prevents new ones from starting.
+	fn stop(&self) {
+		let running = self.pids.lock().unwrap();
+		if !self.stop.swap(true, Ordering::SeqCst) {
+			running.iter().for_each(|p| kill_group(*p));
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Stream {
+	Stdout,
+	Stderr
+}
+
+/// Quotes `s` so a POSIX shell treats it as a single argument.
+fn quote(s: &str) -> RhaiResult<String> {
+	Ok(shell_words::quote(s).into_owned())
+}
+
+fn spawn_err(prog: &str, e: io::Error) -> RhaiError {
+	let hint = match e.kind() {
+		io::ErrorKind::NotFound => match fs::which(prog) {
+			Ok(p) if !p.is_empty() => format!(
+				"{:?} was found at {:?}, maybe the directory is wrong",
+				prog, p
+			),
+			_ => format!("is {:?} installed and in the PATH?", prog)
+		},
+		io::ErrorKind::PermissionDenied => {
+			format!("is {:?} executable?", prog)
+		},
+		_ => return err!("failed to execute {:?}: {}", prog, e)
+	};
+
+	err!("failed to execute {:?}: {}\nhint: {}", prog, e, hint)
+}
+
+/// The spawned processes of a pipeline.
+struct Running {
+	children: Vec<Child>,
+	/// the thread writing to stdin
+	stdin: Option<JoinHandle<()>>,
+	watchdog: Option<Watchdog>,
+	/// every process has its own process group
+	group: bool
+}
+
+impl Running {
+	fn kill(&mut self) {
+		for child in &mut self.children {
+			if self.group {
+				kill_group(child.id());
+			} else {
+				let _ = child.kill();
+			}
+			let _ = child.wait();
+			unregister_group(child.id());
+		}
+	}
+
+	/// Returns true if any process has not exited yet.
+	fn is_running(&mut self) -> RhaiResult<bool> {
+		for child in &mut self.children {
+			if child.try_wait().map_err(io_err)?.is_none() {
+				return Ok(true)
+			}
+		}
+
+		Ok(false)
+	}
+
+	/// Waits until every process exited, calling `on_line` for every line
+	/// written to a piped stdout or stderr.
+	///
+	/// If `on_line` returns false the processes get killed.
+	fn finish_lines(
+		mut self,
+		mut on_line: impl FnMut(Stream, &str) -> bool
+	) -> RhaiResult<Finished> {
+		let (sender, recv) = mpsc::channel();
+		let mut readers = vec![];
+		for child in &mut self.children {
+			if let Some(out) = child.stdout.take() {
+				readers.push(read_lines(out, Stream::Stdout, sender.clone()));
+			}
+			if let Some(err) = child.stderr.take() {
+				readers.push(read_lines(err, Stream::Stderr, sender.clone()));
+			}
+		}
+		drop(sender);
+
+		let mut killed = false;
+		for (stream, line) in recv {
+			if !killed && !on_line(stream, &line) {
+				self.kill();
+				killed = true;
+			}
+		}
+
+		for reader in readers {
+			let _ = reader.join();
+		}
+
+		self.wait(vec![], vec![])
+	}
+
+	/// Waits until every process exited, collecting the stdout of the last
+	/// process and the stderr of every process if they were piped.
+	fn finish(mut self) -> RhaiResult<Finished> {
+		let stderrs: Vec<_> = self.children.iter_mut()
+			.filter_map(|c| c.stderr.take())
+			.map(read_in_thread)
+			.collect();
+
+		let mut stdout = vec![];
+		if let Some(mut out) = self.children.last_mut()
+			.and_then(|c| c.stdout.take())
+		{
+			out.read_to_end(&mut stdout)
+				.map_err(io_err)?;
+		}
+
+		let mut stderr = vec![];
+		for handle in stderrs {
+			stderr.extend(handle.join().unwrap_or_default());
+		}
+
+		self.wait(stdout, stderr)
+	}
+
+	fn wait(
+		&mut self,
+		stdout: Vec<u8>,
+		stderr: Vec<u8>
+	) -> RhaiResult<Finished> {
+		let mut codes = Vec::with_capacity(self.children.len());
+		for child in &mut self.children {
+			let status = child.wait()
+				.map_err(io_err)?;
+			unregister_group(child.id());
+			codes.push(status.code());
+		}
+
+		let timed_out = self.watchdog.take()
+			.map(Watchdog::stop)
+			.unwrap_or(false);
+
+		if let Some(stdin) = self.stdin.take() {
+			let _ = stdin.join();
+		}
+
+		Ok(Finished { stdout, stderr, codes, timed_out })
+	}
+}
+
+/// Kills processes once the timeout is reached.
+struct Watchdog {
+	cancel: mpsc::Sender<()>,
+	timed_out: Arc<AtomicBool>,
+	handle: JoinHandle<()>
+}
+
+impl Watchdog {
+	fn start(pids: Vec<u32>, ms: u64) -> Self {
+		let (cancel, recv) = mpsc::channel();
+		let timed_out = Arc::new(AtomicBool::new(false));
+
+		let timed_out_2 = timed_out.clone();
+		let handle = thread::spawn(move || {
+			if recv.recv_timeout(Duration::from_millis(ms)).is_ok() {
+				return
+			}
+
+			timed_out_2.store(true, Ordering::SeqCst);
+			for pid in pids {
+				kill_group(pid);
+			}
+		});
+
+		Self { cancel, timed_out, handle }
+	}
+
+	/// Returns true if the timeout was reached.
+	fn stop(self) -> bool {
+		let _ = self.cancel.send(());
+		let _ = self.handle.join();
+		self.timed_out.load(Ordering::SeqCst)
+	}
+}
+
+/// process groups which were started with a timeout
+/// they don't receive ctrl-c from the terminal so we need to kill them
+static GROUPS: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+
+static CTRLC: Once = Once::new();
+
+fn register_group(pid: u32) {
+	GROUPS.lock().unwrap().push(pid);
+}
+
+fn unregister_group(pid: u32) {
+	GROUPS.lock().unwrap().retain(|p| *p != pid);
+}
+
+/// How long the process groups get to exit after ctrl-c before they are
+/// killed.
+const CTRLC_GRACE: Duration = Duration::from_secs(3);
+
+/// Terminates all registered process groups and exits once ctrl-c is
+/// pressed.
+///
+/// Children in our process group receive ctrl-c themselves.
+fn handle_ctrlc() {
+	CTRLC.call_once(|| {
+		let _ = ctrlc::set_handler(|| {
+			let pids: Vec<_> = GROUPS.lock().unwrap().drain(..).collect();
+			terminate_groups(&pids);
+			fs::cleanup_temp();
+			std::process::exit(130);
+		});
+	});
+}
+
+#[cfg(unix)]
+fn new_process_group(cmd: &mut Command) {
+	use std::os::unix::process::CommandExt;
+	cmd.process_group(0);
+}
+
+#[cfg(not(unix))]
+fn new_process_group(_cmd: &mut Command) {}
+
+/// Kills the process and every process it started.
+#[cfg(unix)]
+fn kill_group(pid: u32) {
+	// the process group id is the same as the pid of its leader
+	unsafe {
+		libc::kill(-(pid as i32), libc::SIGKILL);
+	}
+}
+
+/// Asks every process group to exit and kills the ones which are still
+/// running after `CTRLC_GRACE`.
+#[cfg(unix)]
+fn terminate_groups(pids: &[u32]) {
+	use std::time::Instant;
+
+	let signal = |pid: u32, sig| unsafe { libc::kill(-(pid as i32), sig) == 0 };
+
+	let mut running: Vec<_> = pids.iter()
+		.copied()
+		.filter(|pid| signal(*pid, libc::SIGTERM))
+		.collect();
+
+	let start = Instant::now();
+	while !running.is_empty() && start.elapsed() < CTRLC_GRACE {
+		thread::sleep(Duration::from_millis(50));
+		// signal 0 only checks if the group still exists
+		running.retain(|pid| signal(*pid, 0));
+	}
+
+	for pid in running {
+		kill_group(pid);
+	}
+}
+
+#[cfg(not(unix))]
+fn terminate_groups(pids: &[u32]) {
+	for pid in pids {
+		kill_group(*pid);
+	}
+}
+
+#[cfg(not(unix))]
+fn kill_group(pid: u32) {
+	let _ = Command::new("taskkill")
+		.args(["/F", "/T", "/PID", &pid.to_string()])
+		.stdout(Stdio::null())
+		.stderr(Stdio::null())
+		.status();
+}
+
+fn read_lines<R>(
+	r: R,
+	stream: Stream,
+	sender: mpsc::Sender<(Stream, String)>
+) -> JoinHandle<()>
+where R: Read + Send + 'static {
+	thread::spawn(move || {
+		for line in BufReader::new(r).split(b'\n') {
+			let line = match line {
+				Ok(l) => l,
+				Err(_) => break
+			};
+			let line = String::from_utf8_lossy(&line);
+			let line = line.strip_suffix('\r').unwrap_or(&line);
+			if sender.send((stream, line.to_string())).is_err() {
+				break
+			}
+		}
+	})
+}
+
+fn read_in_thread<R>(mut r: R) -> JoinHandle<Vec<u8>>
+where R: Read + Send + 'static {
+	thread::spawn(move || {
+		let mut buf = vec![];
+		let _ = r.read_to_end(&mut buf);
+		buf
+	})
+}
+
+#[derive(Clone)]
+struct Finished {
+	stdout: Vec<u8>,
+	stderr: Vec<u8>,
+	/// none if the process was terminated by a signal
+	codes: Vec<Option<i32>>,
+	timed_out: bool
+}
+
+impl Finished {
+	fn success(&self) -> bool {
+		self.codes.iter().all(|c| *c == Some(0))
+	}
+
+	fn into_map(self) -> Map {
+		let success = self.success();
+		let codes: Array = self.codes.into_iter()
+			.map(|c| Dynamic::from(c.map(i64::from).unwrap_or(-1)))
+			.collect();
+		let code = codes.last().cloned().unwrap_or_else(|| Dynamic::from(-1i64));
+
+		let mut map = Map::new();
+		map.insert("stdout".into(), String::from_utf8_lossy(&self.stdout).into_owned().into());
+		map.insert("stderr".into(), String::from_utf8_lossy(&self.stderr).into_owned().into());
+		map.insert("code".into(), code);
+		map.insert("codes".into(), codes.into());
+		map.insert("success".into(), success.into());
+		map
+	}
+}
+
+/// A command started in the background with `start`.
+#[derive(Clone)]
+struct Process {
+	inner: Rc<RefCell<ProcessInner>>
+}
+
+struct ProcessInner {
+	cmd: Cmd,
+	/// pid of the first process
+	pid: u32,
+	running: Option<Running>,
+	/// threads reading stdout and stderr
+	stdout: Option<JoinHandle<Vec<u8>>>,
+	stderrs: Vec<JoinHandle<Vec<u8>>>,
+	finished: Option<Finished>,
+	killed: bool
+}
+
+impl Process {
+	fn finished(cmd: Cmd, finished: Finished) -> Self {
+		Self {
+			inner: Rc::new(RefCell::new(ProcessInner {
+				cmd,
+				pid: 0,
+				running: None,
+				stdout: None,
+				stderrs: vec![],
+				finished: Some(finished),
+				killed: false
+			}))
+		}
+	}
+
+	pub fn pid(&mut self) -> i64 {
+		self.inner.borrow().pid.into()
+	}
+
+	pub fn is_running(&mut self) -> RhaiResult<bool> {
+		match &mut self.inner.borrow_mut().running {
+			Some(running) => running.is_running(),
+			None => Ok(false)
+		}
+	}
+
+	/// Waits until the process exited and returns the same map as `run`.
+	pub fn wait(&mut self) -> RhaiResult<Map> {
+		let mut inner = self.inner.borrow_mut();
+		let finished = inner.finish()?;
+		Ok(finished.clone().into_map())
+	}
+
+	/// Waits until the process exited and returns its stdout.
+	///
+	/// Does not fail if the process exited with a non-zero status.
+	pub fn output(&mut self) -> RhaiResult<String> {
+		let mut inner = self.inner.borrow_mut();
+		let cmd_str = inner.cmd.cmd_str();
+		let finished = inner.finish()?;
+		String::from_utf8(finished.stdout.clone())
+			.map_err(|_| err!("output of {:?} is not valid utf8", cmd_str))
+	}
+
+	/// Kills the process and everything it started.
+	pub fn kill(&mut self) -> RhaiResult<()> {
+		let inner = &mut *self.inner.borrow_mut();
+		if let Some(running) = &mut inner.running {
+			paint_act!("killing {:?}", inner.cmd.cmd_str());
+			running.kill();
+			inner.killed = true;
+		}
+		inner.finish()?;
 		Ok(())
 	}
 }
 
+impl ProcessInner {
+	/// Waits until the process exited if this was not already done.
+	fn finish(&mut self) -> RhaiResult<&Finished> {
+		if let Some(mut running) = self.running.take() {
+			let stdout = self.stdout.take()
+				.map(|h| h.join().unwrap_or_default())
+				.unwrap_or_default();
+			let mut stderr = vec![];
+			for handle in self.stderrs.drain(..) {
+				stderr.extend(handle.join().unwrap_or_default());
+			}
+
+			let finished = running.wait(stdout, stderr)?;
+			let cmd_str = self.cmd.cmd_str();
+			if self.killed {
+				paint_ok!("execution {:?} killed", cmd_str);
+			} else if finished.success() {
+				paint_ok!("execution {:?} successful", cmd_str);
+			} else if !finished.timed_out {
+				paint_err!("execution {:?} failed", cmd_str);
+			}
+			self.finished = Some(finished);
+		}
+
+		let finished = self.finished.as_ref()
+			.expect("running or finished");
+		if finished.timed_out {
+			self.cmd.check_timeout(finished.clone())?;
+		}
+
+		Ok(finished)
+	}
+}
+
+impl Drop for ProcessInner {
+	fn drop(&mut self) {
+		if let Some(mut running) = self.running.take() {
+			running.kill();
+			let _ = running.wait(vec![], vec![]);
+		}
+	}
+}
+
 pub fn add(engine: &mut Engine) {
+	handle_ctrlc();
+
 	let mut cmd_mod = Module::new();
 	cmd_mod.set_native_fn("execute_parallel", Cmd::execute_parallel);
+	cmd_mod.set_native_fn("execute_parallel", Cmd::execute_parallel_with);
+	cmd_mod.set_native_fn("quote", quote);
 
 	engine
 		.register_fn("cmd", Cmd::new)
 		.register_fn("cmd", Cmd::with_args)
+		.register_fn("sh", Cmd::from_shell)
 		.register_fn("arg", Cmd::arg)
 		.register_fn("args", Cmd::args)
 		.register_fn("dir", Cmd::dir)
 		.register_fn("env", Cmd::env)
+		.register_fn("env_remove", Cmd::env_remove)
 		.register_fn("env_clear", Cmd::env_clear)
+		.register_fn("stdin", Cmd::stdin)
+		.register_fn("stdin_file", Cmd::stdin_file)
+		.register_fn("pipe", Cmd::pipe)
+		.register_fn("timeout", Cmd::timeout)
+		.register_fn("name", Cmd::name)
+		.register_fn("retry", Cmd::retry)
 		.register_fn("execute", Cmd::execute)
 		.register_fn("output", Cmd::output)
+		.register_fn("run", Cmd::run)
+		.register_fn("stream", Cmd::stream)
+		.register_fn("start", Cmd::start)
+		.register_fn("pid", Process::pid)
+		.register_fn("is_running", Process::is_running)
+		.register_fn("wait", Process::wait)
+		.register_fn("output", Process::output)
+		.register_fn("kill", Process::kill)
 		.register_static_module("cmd", cmd_mod.into());
-}
\ No newline at end of file
+}
@@ -5,10 +5,22 @@ use std::{fs, env};
 use std::io::Write;
 use std::fs::OpenOptions;
 use std::path::{Path, PathBuf};
-#[cfg(target_family = "unix")]
-use std::process::Command;
 
-use rhai::{Array, Engine, ImmutableString, Module};
+use rhai::{Array, Blob, Engine, ImmutableString, Map, Module};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+
+mod copy;
+mod walk;
+mod meta;
+mod hash;
+mod atomic;
+mod transaction;
+mod temp;
+
+pub(crate) use atomic::write as write_atomic;
+pub(crate) use transaction::end as end_transaction;
+pub use temp::{set_keep_temp, cleanup_temp};
+pub(crate) use walk::{entries as walk_entries, path_string};
 
 fn create_dir(s: &str) -> RhaiResult<()> {
 	if is_dir(s)? {
@@ -16,6 +28,9 @@ fn create_dir(s: &str) -> RhaiResult<()> {
 	}
 
 	paint_act!("create directory {:?}", s);
+	dry_run!(Ok(()));
+	transaction::record_dirs(s.as_ref())
+		.map_err(io_err)?;
 	fs::create_dir_all(s)
 		.map_err(io_err)
 }
@@ -32,7 +47,7 @@ fn is_prog(s: &str) -> RhaiResult<bool> {
 	which(s).map(|s| !s.is_empty())
 }
 
-fn which(s: &str) -> RhaiResult<String> {
+pub(crate) fn which(s: &str) -> RhaiResult<String> {
 	if is_file(&format!("./{}", s))? {
 		return full_path(&format!("./{}", s));
 	}
@@ -63,7 +78,18 @@ fn full_path(s: &str) -> RhaiResult<String> {
 }
 
 pub(crate) fn write_file_str(path: &str, s: &str) -> RhaiResult<()> {
-	fs::write(path, s)
+	write_file_bytes(path, s.as_bytes())
+}
+
+fn write_file_blob(path: &str, blob: Blob) -> RhaiResult<()> {
+	write_file_bytes(path, &blob)
+}
+
+fn write_file_bytes(path: &str, bytes: &[u8]) -> RhaiResult<()> {
+	paint_act!("write {:?}", path);
+	dry_run!(Ok(()));
+
+	atomic::write(path, bytes)
 		.map_err(|e| err!("could not write to {} error {:?}", path, e))
 }
 
@@ -72,6 +98,11 @@ pub(crate) fn read_file(path: &str) -> RhaiResult<String> {
 		.map_err(|e| err!("could not read file {} error {:?}", path, e))
 }
 
+fn read_blob(path: &str) -> RhaiResult<Blob> {
+	fs::read(path)
+		.map_err(|e| err!("could not read file {} error {:?}", path, e))
+}
+
 fn read_dir(path: &str) -> RhaiResult<Array> {
 	fs::read_dir(path)
 		.map_err(io_err)?
@@ -107,13 +138,25 @@ fn write_file_arr(path: &str, arr: Array) -> RhaiResult<()> {
 }
 
 fn append_str(path: &str, s: &str) -> RhaiResult<()> {
+	append_bytes(path, s.as_bytes())
+}
+
+fn append_blob(path: &str, blob: Blob) -> RhaiResult<()> {
+	append_bytes(path, &blob)
+}
+
+fn append_bytes(path: &str, bytes: &[u8]) -> RhaiResult<()> {
+	paint_act!("append to {:?}", path);
+	dry_run!(Ok(()));
+
+	transaction::record(path.as_ref())
+		.map_err(io_err)?;
 	OpenOptions::new()
-		.write(true)
 		.create(true)
 		.append(true)
 		.open(path)
 		.map_err(io_err)?
-		.write_all(s.as_bytes())
+		.write_all(bytes)
 		.map_err(io_err)
 }
 
@@ -127,10 +170,16 @@ fn delete(path: &str) -> RhaiResult<()> {
 	let p = Path::new(path);
 	if p.is_dir() {
 		paint_act!("delete directory {:?}", path);
+		dry_run!(Ok(()));
+		transaction::record(p)
+			.map_err(io_err)?;
 		fs::remove_dir_all(p)
 			.map_err(io_err)
 	} else if p.is_file() {
 		paint_act!("delete file {:?}", path);
+		dry_run!(Ok(()));
+		transaction::record(p)
+			.map_err(io_err)?;
 		fs::remove_file(p)
 			.map_err(io_err)
 	} else {
@@ -141,40 +190,99 @@ fn delete(path: &str) -> RhaiResult<()> {
 // deletes a file or a folder
 fn rename(from: &str, to: &str) -> RhaiResult<()> {
 	paint_act!("move {:?} to {:?}", from, to);
+	dry_run!(Ok(()));
+	transaction::record(from.as_ref())
+		.map_err(io_err)?;
+	if is_dir(from)? {
+		transaction::record_dirs(to.as_ref())
+			.map_err(io_err)?;
+		transaction::record_tree(from.as_ref(), to.as_ref())
+	} else {
+		transaction::record(to.as_ref())
+	}.map_err(io_err)?;
+
 	fs::rename(from, to)
 		.map_err(io_err)
 }
 
-// for the moment let's use cp command
-#[cfg(target_family = "unix")]
-fn copy(from: &str, to: &str) -> RhaiResult<()> {
-	paint_act!("copy {:?} to {:?}", from, to);
+/// Returns the bool option `key` or `default` if it is not set.
+pub(crate) fn opt_bool(opts: &Map, key: &str, default: bool) -> RhaiResult<bool> {
+	match opts.get(key) {
+		Some(v) => v.as_bool()
+			.map_err(|t| err!("{} expected bool found {}", key, t)),
+		None => Ok(default)
+	}
+}
 
-	let status = Command::new("cp")
-		.args(&["-r", from, to])
-		.status()
-		.expect("failed to execute");
+/// Include and exclude globs from the options `include` and `exclude`
+/// which are matched against relative paths.
+///
+/// Both options can either be a string or an array of strings.
+#[derive(Default)]
+pub(crate) struct Filter {
+	include: Option<GlobSet>,
+	exclude: Option<GlobSet>
+}
 
-	if status.success() {
-		Ok(())
-	} else {
-		Err(err!("execution {:?} failed", status))
+impl Filter {
+	pub(crate) fn from_opts(opts: &Map) -> RhaiResult<Self> {
+		Ok(Self {
+			include: glob_set(opts, "include")?,
+			exclude: glob_set(opts, "exclude")?
+		})
+	}
+
+	pub(crate) fn has_include(&self) -> bool {
+		self.include.is_some()
+	}
+
+	pub(crate) fn is_excluded(&self, rel: &Path) -> bool {
+		self.exclude.as_ref()
+			.map(|e| e.is_match(rel))
+			.unwrap_or(false)
+	}
+
+	/// Returns true if `rel` is included and not excluded.
+	pub(crate) fn matches(&self, rel: &Path) -> bool {
+		let included = self.include.as_ref()
+			.map(|i| i.is_match(rel))
+			.unwrap_or(true);
+		included && !self.is_excluded(rel)
 	}
 }
 
-// https://stackoverflow.com/a/65192210
-// probably should be replaced with
-#[cfg(not(target_family = "unix"))]
-fn copy(from: &str, to: &str) -> RhaiResult<()> {
-	fs::copy(from, to)
-		.map(|_| ())
-		.map_err(io_err)
+fn glob_set(opts: &Map, key: &str) -> RhaiResult<Option<GlobSet>> {
+	let globs = match opts.get(key) {
+		Some(v) if v.is_string() => vec![v.clone().into_string()?],
+		Some(v) if v.is_array() => v.clone().into_array()?
+			.into_iter()
+			.map(|g| g.into_string())
+			.collect::<Result<_, _>>()?,
+		Some(v) => return Err(err!(
+			"{} expected string or array found {}", key, v.type_name()
+		)),
+		None => return Ok(None)
+	};
+
+	let mut builder = GlobSetBuilder::new();
+	for glob in globs {
+		builder.add(
+			Glob::new(&glob)
+				.map_err(|e| err!("invalid glob {:?}: {}", glob, e))?
+		);
+	}
+
+	builder.build()
+		.map(Some)
+		.map_err(|e| err!("{}: {}", key, e))
 }
 
+// works on non utf8 files
 fn contains(path: &str, patt: &str) -> RhaiResult<bool> {
-	let s = fs::read_to_string(path)
+	let bytes = fs::read(path)
 		.map_err(io_err)?;
-	Ok(s.contains(patt))
+	let patt = patt.as_bytes();
+	Ok(patt.is_empty() || bytes.windows(patt.len()).any(|w| w == patt))
 }
 
 pub fn add(engine: &mut Engine) {
@@ -185,15 +293,33 @@ pub fn add(engine: &mut Engine) {
 	fs_mod.set_native_fn("create_dir", create_dir);
 	fs_mod.set_native_fn("write", write_file_str);
 	fs_mod.set_native_fn("write", write_file_arr);
+	fs_mod.set_native_fn("write", write_file_blob);
 	fs_mod.set_native_fn("read", read_file);
+	fs_mod.set_native_fn("read_blob", read_blob);
 	fs_mod.set_native_fn("delete", delete);
 	fs_mod.set_native_fn("move", rename);
-	fs_mod.set_native_fn("copy", copy);
+	fs_mod.set_native_fn("copy", copy::copy);
+	fs_mod.set_native_fn("copy", copy::copy_with);
 	fs_mod.set_native_fn("full_path", full_path);
 	fs_mod.set_native_fn("append", append_str);
 	fs_mod.set_native_fn("append", append_arr);
+	fs_mod.set_native_fn("append", append_blob);
 	fs_mod.set_native_fn("contains", contains);
 	fs_mod.set_native_fn("read_dir", read_dir);
+	fs_mod.set_native_fn("walk", walk::walk);
+	fs_mod.set_native_fn("walk", walk::walk_with);
+	fs_mod.set_native_fn("glob", walk::glob);
+	fs_mod.set_native_fn("stat", meta::stat);
+	fs_mod.set_native_fn("chmod", meta::chmod);
+	fs_mod.set_native_fn("symlink", meta::symlink);
+	fs_mod.set_native_fn("read_link", meta::read_link);
+	fs_mod.set_native_fn("is_symlink", meta::is_symlink);
+	fs_mod.set_native_fn("hash", hash::hash);
+	fs_mod.set_native_fn("set_backup", atomic::set_backup);
+	fs_mod.set_native_fn("transaction", transaction::begin);
+	fs_mod.set_native_fn("temp_dir", temp::temp_dir);
+	fs_mod.set_native_fn("temp_file", temp::temp_file);
+	fs_mod.set_native_fn("temp_file", temp::temp_file_with);
 	fs_mod.set_native_fn("which", which);
 	engine
 		.register_static_module("fs", fs_mod.into());
@@ -0,0 +1,88 @@
+use super::transaction;
+use crate::api::RhaiResult;
+
+use std::{fs, io};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use rand::Rng;
+
+static BACKUP: AtomicBool = AtomicBool::new(false);
+
+/// If enabled, files overwritten by `fs::write` or `replace_file` are
+/// first copied to `<file>.bak`.
+pub fn set_backup(backup: bool) -> RhaiResult<()> {
+	BACKUP.store(backup, Ordering::Relaxed);
+	Ok(())
+}
+
+/// Writes `bytes` to a temporary file next to `path` and then renames it,
+/// so `path` either contains the old or the new content.
+///
+/// The permissions of an existing file are kept and a symlink is written
+/// through.
+pub(crate) fn write(path: &str, bytes: &[u8]) -> io::Result<()> {
+	let path = resolve_symlink(Path::new(path))?;
+	transaction::record(&path)?;
+
+	let perms = fs::metadata(&path).ok()
+		.filter(|m| m.is_file())
+		.map(|m| m.permissions());
+
+	if perms.is_some() && BACKUP.load(Ordering::Relaxed) {
+		let mut bak = path.clone().into_os_string();
+		bak.push(".bak");
+		paint_act!("backup {:?} to {:?}", path, bak);
+		transaction::record(Path::new(&bak))?;
+		fs::copy(&path, &bak)?;
+	}
+
+	let tmp = temp_path(&path);
+	let r = write_tmp(&tmp, bytes, perms)
+		.and_then(|_| fs::rename(&tmp, &path));
+	if r.is_err() {
+		let _ = fs::remove_file(&tmp);
+	}
+
+	r
+}
+
+fn write_tmp(
+	tmp: &Path,
+	bytes: &[u8],
+	perms: Option<fs::Permissions>
+) -> io::Result<()> {
+	let mut file = fs::File::create(tmp)?;
+	file.write_all(bytes)?;
+	file.sync_all()?;
+	if let Some(perms) = perms {
+		fs::set_permissions(tmp, perms)?;
+	}
+
+	Ok(())
+}
+
+fn resolve_symlink(path: &Path) -> io::Result<PathBuf> {
+	let is_symlink = fs::symlink_metadata(path)
+		.map(|m| m.file_type().is_symlink())
+		.unwrap_or(false);
+
+	if is_symlink {
+		// the target might not exist yet
+		let target = fs::read_link(path)?;
+		Ok(path.parent().unwrap_or(Path::new("")).join(target))
+	} else {
+		Ok(path.to_path_buf())
+	}
+}
+
+/// Returns a hidden path in the same directory, so the rename stays on
+/// the same filesystem.
+fn temp_path(path: &Path) -> PathBuf {
+	let name = path.file_name()
+		.map(|n| n.to_string_lossy().into_owned())
+		.unwrap_or_default();
+	let rand: u32 = rand::thread_rng().gen();
+	path.with_file_name(format!(".{}.{:08x}.tmp", name, rand))
+}
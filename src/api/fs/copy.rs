@@ -0,0 +1,251 @@
+use super::{Filter, opt_bool, transaction};
+use crate::api::{RhaiResult, RhaiError};
+
+use std::{env, fs, io};
+use std::path::{Path, PathBuf};
+
+use rhai::Map;
+
+struct CopyOpts {
+	/// replace files which already exist
+	overwrite: bool,
+	/// if overwrite is false, silently skip existing files instead of failing
+	skip_existing: bool,
+	/// copy the permissions of files and directories
+	permissions: bool,
+	/// copy symlinks as symlinks instead of following them
+	symlinks: bool,
+	filter: Filter
+}
+
+impl CopyOpts {
+	fn from_map(opts: &Map) -> RhaiResult<Self> {
+		Ok(Self {
+			overwrite: opt_bool(opts, "overwrite", true)?,
+			skip_existing: opt_bool(opts, "skip_existing", false)?,
+			permissions: opt_bool(opts, "permissions", true)?,
+			symlinks: opt_bool(opts, "symlinks", true)?,
+			filter: Filter::from_opts(opts)?
+		})
+	}
+}
+
+pub fn copy(from: &str, to: &str) -> RhaiResult<()> {
+	copy_with(from, to, Map::new())
+}
+
+/// Copies a file or a directory recursively.
+///
+/// Like `cp -r`, if `to` is an existing directory `from` is copied into it.
+///
+/// Options:
+/// - `overwrite`: replace existing files (default true)
+/// - `skip_existing`: skip existing files if overwrite is false, instead
+///   of failing (default false)
+/// - `permissions`: copy permissions (default true)
+/// - `symlinks`: copy symlinks instead of following them (default true)
+/// - `include`, `exclude`: globs matched against the path relative to
+///   `from`, an excluded directory is skipped entirely
+pub fn copy_with(from: &str, to: &str, opts: Map) -> RhaiResult<()> {
+	paint_act!("copy {:?} to {:?}", from, to);
+	let opts = CopyOpts::from_map(&opts)?;
+	dry_run!(Ok(()));
+
+	let src = Path::new(from);
+	let mut dst = PathBuf::from(to);
+	if dst.is_dir() {
+		if let Some(name) = src.file_name() {
+			dst.push(name);
+		}
+	}
+
+	let meta = metadata(src, opts.symlinks)
+		.map_err(|e| copy_err(src, &dst, e))?;
+
+	if meta.is_dir() {
+		if is_inside(src, &dst).map_err(|e| copy_err(src, &dst, e))? {
+			return Err(err!(
+				"copy: cannot copy {:?} into itself {:?}",
+				src, dst
+			))
+		}
+		copy_dir(src, &dst, Path::new(""), &opts)
+	} else {
+		if is_same_file(src, &dst) {
+			return Err(err!(
+				"copy: {:?} and {:?} are the same file",
+				src, dst
+			))
+		}
+		copy_entry(src, &dst, &meta, &opts)
+	}
+}
+
+fn metadata(path: &Path, symlinks: bool) -> io::Result<fs::Metadata> {
+	if symlinks {
+		fs::symlink_metadata(path)
+	} else {
+		fs::metadata(path)
+	}
+}
+
+/// Returns true if both paths exist and resolve to the same file, copying
+/// would truncate it.
+fn is_same_file(src: &Path, dst: &Path) -> bool {
+	match (src.canonicalize(), dst.canonicalize()) {
+		(Ok(src), Ok(dst)) => src == dst,
+		_ => false
+	}
+}
+
+/// Returns true if `dst` is `dir` or below it, `dst` does not need to
+/// exist.
+fn is_inside(dir: &Path, dst: &Path) -> io::Result<bool> {
+	let dir = dir.canonicalize()?;
+	// canonicalize the nearest existing ancestor and append the rest
+	let abs = env::current_dir()?.join(dst);
+	let mut rest = vec![];
+	let mut existing = abs.as_path();
+	while !existing.exists() {
+		match (existing.parent(), existing.file_name()) {
+			(Some(parent), Some(name)) => {
+				rest.push(name);
+				existing = parent;
+			},
+			_ => break
+		}
+	}
+
+	let mut dst = existing.canonicalize()?;
+	dst.extend(rest.into_iter().rev());
+	Ok(dst.starts_with(dir))
+}
+
+fn copy_dir(
+	src: &Path,
+	dst: &Path,
+	rel: &Path,
+	opts: &CopyOpts
+) -> RhaiResult<()> {
+	// with include globs, directories are only created if they get a file
+	if !opts.filter.has_include() || rel.as_os_str().is_empty() {
+		transaction::record_dirs(dst)
+			.and_then(|_| fs::create_dir_all(dst))
+			.map_err(|e| copy_err(src, dst, e))?;
+	}
+
+	let mut entries = fs::read_dir(src)
+		.and_then(|rd| rd.collect::<io::Result<Vec<_>>>())
+		.map_err(|e| copy_err(src, dst, e))?;
+	entries.sort_by_key(|e| e.file_name());
+
+	for entry in entries {
+		let name = entry.file_name();
+		let src = entry.path();
+		let dst = dst.join(&name);
+		let rel = rel.join(&name);
+
+		let meta = metadata(&src, opts.symlinks)
+			.map_err(|e| copy_err(&src, &dst, e))?;
+
+		if meta.is_dir() {
+			if !opts.filter.is_excluded(&rel) {
+				copy_dir(&src, &dst, &rel, opts)?;
+			}
+		} else if opts.filter.matches(&rel) {
+			if let Some(parent) = dst.parent() {
+				transaction::record_dirs(parent)
+					.and_then(|_| fs::create_dir_all(parent))
+					.map_err(|e| copy_err(&src, &dst, e))?;
+			}
+			copy_entry(&src, &dst, &meta, opts)?;
+		}
+	}
+
+	// set after the content was copied, in case the directory is read-only
+	if opts.permissions && dst.is_dir() {
+		let perms = fs::metadata(src)
+			.map_err(|e| copy_err(src, dst, e))?
+			.permissions();
+		fs::set_permissions(dst, perms)
+			.map_err(|e| copy_err(src, dst, e))?;
+	}
+
+	Ok(())
+}
+
+/// Copies a file or a symlink.
+fn copy_entry(
+	src: &Path,
+	dst: &Path,
+	meta: &fs::Metadata,
+	opts: &CopyOpts
+) -> RhaiResult<()> {
+	if let Ok(existing) = fs::symlink_metadata(dst) {
+		if !opts.overwrite {
+			if opts.skip_existing {
+				return Ok(())
+			}
+
+			return Err(err!("copy: {:?} already exists", dst))
+		}
+
+		transaction::record(dst)
+			.map_err(|e| copy_err(src, dst, e))?;
+
+		// fs::copy would write through a symlink
+		if existing.file_type().is_symlink() {
+			fs::remove_file(dst)
+				.map_err(|e| copy_err(src, dst, e))?;
+		}
+	} else {
+		transaction::record(dst)
+			.map_err(|e| copy_err(src, dst, e))?;
+	}
+
+	let r = if meta.file_type().is_symlink() {
+		copy_symlink(src, dst)
+	} else if opts.permissions {
+		fs::copy(src, dst).map(|_| ())
+	} else {
+		copy_content(src, dst)
+	};
+
+	r.map_err(|e| copy_err(src, dst, e))
+}
+
+/// Copies only the content, so the file gets the default permissions.
+fn copy_content(src: &Path, dst: &Path) -> io::Result<()> {
+	let mut from = fs::File::open(src)?;
+	let mut to = fs::File::create(dst)?;
+	io::copy(&mut from, &mut to)
+		.map(|_| ())
+}
+
+#[cfg(unix)]
+fn copy_symlink(src: &Path, dst: &Path) -> io::Result<()> {
+	let target = fs::read_link(src)?;
+	if dst.exists() {
+		fs::remove_file(dst)?;
+	}
+	std::os::unix::fs::symlink(target, dst)
+}
+
+#[cfg(windows)]
+fn copy_symlink(src: &Path, dst: &Path) -> io::Result<()> {
+	use std::os::windows::fs::{symlink_dir, symlink_file};
+
+	let target = fs::read_link(src)?;
+	if dst.exists() {
+		fs::remove_file(dst)?;
+	}
+	if src.is_dir() {
+		symlink_dir(target, dst)
+	} else {
+		symlink_file(target, dst)
+	}
+}
+
+fn copy_err(src: &Path, dst: &Path, e: io::Error) -> RhaiError {
+	err!("could not copy {:?} to {:?}: {}", src, dst, e)
+}
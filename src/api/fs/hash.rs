@@ -0,0 +1,85 @@
+use super::walk::{all_files, path_string};
+use crate::api::{RhaiResult, RhaiError};
+
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::fmt::Write;
+use std::path::Path;
+
+use sha2::{Digest, Sha256, Sha512};
+
+/// Returns the hex encoded hash of a file or a directory, `algo` can be
+/// `sha256` or `sha512`.
+///
+/// A directory is hashed from the relative path and hash of every file
+/// below it, so renaming a file changes the hash. Symlinks in it are
+/// hashed by their target.
+pub fn hash(path: &str, algo: &str) -> RhaiResult<String> {
+	match algo {
+		"sha256" => hash_path::<Sha256>(Path::new(path)),
+		"sha512" => hash_path::<Sha512>(Path::new(path)),
+		_ => Err(err!("hash: unknown algorithm {:?}", algo))
+	}
+}
+
+fn hash_path<D: Digest>(path: &Path) -> RhaiResult<String> {
+	if !path.is_dir() {
+		return hash_file::<D>(path)
+	}
+
+	let mut hasher = D::new();
+	for rel in all_files(path)? {
+		let file = path.join(&rel);
+		let is_symlink = fs::symlink_metadata(&file)
+			.map_err(|e| hash_err(&file, e))?
+			.file_type()
+			.is_symlink();
+
+		hasher.update(path_string(&rel)?.as_bytes());
+		hasher.update(b"\0");
+		if is_symlink {
+			// symlinks are hashed by their target, which might be a
+			// directory or not exist
+			let target = fs::read_link(&file)
+				.map_err(|e| hash_err(&file, e))?;
+			hasher.update(b"symlink\0");
+			hasher.update(target.to_string_lossy().as_bytes());
+		} else {
+			hasher.update(hash_file::<D>(&file)?.as_bytes());
+		}
+		hasher.update(b"\n");
+	}
+
+	Ok(to_hex(&hasher.finalize()))
+}
+
+fn hash_file<D: Digest>(path: &Path) -> RhaiResult<String> {
+	let mut file = File::open(path)
+		.map_err(|e| hash_err(path, e))?;
+
+	let mut hasher = D::new();
+	let mut buf = vec![0; 64 * 1024];
+	loop {
+		let read = file.read(&mut buf)
+			.map_err(|e| hash_err(path, e))?;
+		if read == 0 {
+			break
+		}
+		hasher.update(&buf[..read]);
+	}
+
+	Ok(to_hex(&hasher.finalize()))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+	let mut s = String::with_capacity(bytes.len() * 2);
+	for b in bytes {
+		write!(s, "{b:02x}").unwrap();
+	}
+
+	s
+}
+
+fn hash_err(path: &Path, e: io::Error) -> RhaiError {
+	err!("could not hash {:?}: {}", path, e)
+}
@@ -0,0 +1,134 @@
+use super::{path_to_string, transaction};
+use crate::api::{RhaiResult, RhaiError};
+
+use std::{fs, io};
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use rhai::{Dynamic, Map};
+
+fn meta_err(path: &str, e: io::Error) -> RhaiError {
+	err!("{:?}: {}", path, e)
+}
+
+/// Returns a map with `size`, `modified` (seconds since the unix epoch),
+/// `mode` and `kind` which is one of `file`, `dir`, `symlink` or `other`.
+///
+/// Symlinks are not followed.
+pub fn stat(path: &str) -> RhaiResult<Map> {
+	let meta = fs::symlink_metadata(path)
+		.map_err(|e| meta_err(path, e))?;
+
+	let ft = meta.file_type();
+	let kind = if ft.is_symlink() {
+		"symlink"
+	} else if ft.is_dir() {
+		"dir"
+	} else if ft.is_file() {
+		"file"
+	} else {
+		"other"
+	};
+
+	let modified = meta.modified()
+		.ok()
+		.and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+		.map(|d| d.as_secs() as i64)
+		.unwrap_or(0);
+
+	let mut map = Map::new();
+	map.insert("size".into(), Dynamic::from(meta.len() as i64));
+	map.insert("modified".into(), Dynamic::from(modified));
+	map.insert("mode".into(), Dynamic::from(mode(&meta)));
+	map.insert("kind".into(), kind.into());
+	Ok(map)
+}
+
+#[cfg(unix)]
+fn mode(meta: &fs::Metadata) -> i64 {
+	use std::os::unix::fs::PermissionsExt;
+
+	(meta.permissions().mode() & 0o7777) as i64
+}
+
+// windows only knows readonly
+#[cfg(not(unix))]
+fn mode(meta: &fs::Metadata) -> i64 {
+	if meta.permissions().readonly() {
+		0o444
+	} else {
+		0o666
+	}
+}
+
+/// Sets the permissions, for example `fs::chmod("run.sh", 0o755)`.
+///
+/// On windows only the readonly flag is set if the mode is not writable.
+pub fn chmod(path: &str, mode: i64) -> RhaiResult<()> {
+	if !(0..=0o7777).contains(&mode) {
+		return Err(err!("chmod: invalid mode {:o}", mode))
+	}
+
+	paint_act!("chmod {:o} {:?}", mode, path);
+	dry_run!(Ok(()));
+
+	transaction::record(path.as_ref())
+		.and_then(|_| set_mode(path, mode as u32))
+		.map_err(|e| meta_err(path, e))
+}
+
+#[cfg(unix)]
+fn set_mode(path: &str, mode: u32) -> io::Result<()> {
+	use std::os::unix::fs::PermissionsExt;
+
+	fs::set_permissions(path, fs::Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+fn set_mode(path: &str, mode: u32) -> io::Result<()> {
+	let mut perms = fs::metadata(path)?.permissions();
+	perms.set_readonly(mode & 0o222 == 0);
+	fs::set_permissions(path, perms)
+}
+
+/// Creates a symlink at `link` pointing to `target`.
+///
+/// A relative target is relative to the directory of `link`.
+pub fn symlink(target: &str, link: &str) -> RhaiResult<()> {
+	paint_act!("symlink {:?} to {:?}", link, target);
+	dry_run!(Ok(()));
+
+	transaction::record(link.as_ref())
+		.and_then(|_| create_symlink(target, link))
+		.map_err(|e| meta_err(link, e))
+}
+
+#[cfg(unix)]
+fn create_symlink(target: &str, link: &str) -> io::Result<()> {
+	std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn create_symlink(target: &str, link: &str) -> io::Result<()> {
+	use std::os::windows::fs::{symlink_dir, symlink_file};
+
+	let resolved = Path::new(link).parent()
+		.unwrap_or(Path::new(""))
+		.join(target);
+	if resolved.is_dir() {
+		symlink_dir(target, link)
+	} else {
+		symlink_file(target, link)
+	}
+}
+
+/// Returns the target of a symlink.
+pub fn read_link(path: &str) -> RhaiResult<String> {
+	fs::read_link(path)
+		.map_err(|e| meta_err(path, e))
+		.and_then(path_to_string)
+}
+
+pub fn is_symlink(path: &str) -> RhaiResult<bool> {
+	Ok(Path::new(path).is_symlink())
+}
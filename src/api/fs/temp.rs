@@ -0,0 +1,75 @@
+use super::path_to_string;
+use crate::api::{RhaiResult, io_err};
+
+use std::{env, fs};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use uuid::Uuid;
+
+/// paths which get deleted by cleanup_temp
+static TEMPS: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+static KEEP_TEMP: AtomicBool = AtomicBool::new(false);
+
+/// If enabled temporary files and directories are not deleted.
+pub fn set_keep_temp(keep: bool) {
+	KEEP_TEMP.store(keep, Ordering::Relaxed);
+}
+
+fn temp_path(suffix: &str) -> PathBuf {
+	env::temp_dir().join(format!("riji-{}{}", Uuid::new_v4(), suffix))
+}
+
+/// Creates an empty directory which is deleted when the script finishes.
+pub fn temp_dir() -> RhaiResult<String> {
+	let path = temp_path("");
+	fs::create_dir(&path)
+		.map_err(io_err)?;
+
+	TEMPS.lock().unwrap().push(path.clone());
+	path_to_string(path)
+}
+
+pub fn temp_file() -> RhaiResult<String> {
+	temp_file_with(".tmp")
+}
+
+/// Creates an empty file ending with `suffix` which is deleted when the
+/// script finishes.
+pub fn temp_file_with(suffix: &str) -> RhaiResult<String> {
+	let path = temp_path(suffix);
+	fs::OpenOptions::new()
+		.write(true)
+		.create_new(true)
+		.open(&path)
+		.map_err(io_err)?;
+
+	TEMPS.lock().unwrap().push(path.clone());
+	path_to_string(path)
+}
+
+/// Deletes every temporary file and directory, unless `--keep-temp` was
+/// passed.
+pub fn cleanup_temp() {
+	let temps: Vec<_> = TEMPS.lock().unwrap().drain(..).collect();
+	if temps.is_empty() {
+		return
+	}
+
+	if KEEP_TEMP.load(Ordering::Relaxed) {
+		for path in temps {
+			paint_act!("keeping {:?}", path);
+		}
+		return
+	}
+
+	for path in temps {
+		let _ = if path.is_dir() {
+			fs::remove_dir_all(&path)
+		} else {
+			fs::remove_file(&path)
+		};
+	}
+}
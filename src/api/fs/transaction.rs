@@ -0,0 +1,191 @@
+use crate::api::RhaiResult;
+
+use std::{env, fs, io};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// What a file looked like before it was first touched.
+enum Original {
+	Missing,
+	File(Vec<u8>, fs::Permissions),
+	Symlink(PathBuf)
+}
+
+struct Entry {
+	path: PathBuf,
+	original: Original
+}
+
+/// none if no transaction is active
+static JOURNAL: Mutex<Option<Vec<Entry>>> = Mutex::new(None);
+
+/// Starts a transaction which lasts until the current task finishes.
+///
+/// If the task fails, every file changed by the fs api is restored and
+/// every directory it created is removed.
+pub fn begin() -> RhaiResult<()> {
+	let mut journal = JOURNAL.lock().unwrap();
+	if journal.is_none() {
+		paint_act!("begin transaction");
+		*journal = Some(vec![]);
+	}
+
+	Ok(())
+}
+
+/// Remembers the content of `path` if a transaction is active and the
+/// path was not already recorded. A directory records every file in it.
+pub(crate) fn record(path: &Path) -> io::Result<()> {
+	let mut journal = JOURNAL.lock().unwrap();
+	match journal.as_mut() {
+		Some(entries) => record_path(entries, &env::current_dir()?.join(path)),
+		None => Ok(())
+	}
+}
+
+/// Records every path below `to` which would be created by copying or
+/// moving the directory `from` to `to`.
+pub(crate) fn record_tree(from: &Path, to: &Path) -> io::Result<()> {
+	let mut journal = JOURNAL.lock().unwrap();
+	let entries = match journal.as_mut() {
+		Some(e) => e,
+		None => return Ok(())
+	};
+
+	let cwd = env::current_dir()?;
+	let (from, to) = (cwd.join(from), cwd.join(to));
+	let mut files = vec![];
+	files_below(&from, &mut files)?;
+	for file in files {
+		if let Ok(rel) = file.strip_prefix(&from) {
+			record_path(entries, &to.join(rel))?;
+		}
+	}
+
+	Ok(())
+}
+
+/// Records `dir` and every parent of it which does not exist yet, so they
+/// get removed again, the deepest first, if the transaction fails.
+pub(crate) fn record_dirs(dir: &Path) -> io::Result<()> {
+	let mut journal = JOURNAL.lock().unwrap();
+	let entries = match journal.as_mut() {
+		Some(e) => e,
+		None => return Ok(())
+	};
+
+	let dir = env::current_dir()?.join(dir);
+	let missing: Vec<_> = dir.ancestors()
+		.take_while(|p| fs::symlink_metadata(p).is_err())
+		.collect();
+	// outermost first, the rollback runs in reverse
+	for path in missing.into_iter().rev() {
+		record_path(entries, path)?;
+	}
+
+	Ok(())
+}
+
+fn record_path(entries: &mut Vec<Entry>, path: &Path) -> io::Result<()> {
+	let meta = fs::symlink_metadata(path).ok();
+	if meta.as_ref().map(|m| m.is_dir()).unwrap_or(false) {
+		let mut files = vec![];
+		files_below(path, &mut files)?;
+		for file in files {
+			record_path(entries, &file)?;
+		}
+		return Ok(())
+	}
+
+	if entries.iter().any(|e| e.path == path) {
+		return Ok(())
+	}
+
+	let original = match meta {
+		None => Original::Missing,
+		Some(m) if m.file_type().is_symlink() => {
+			Original::Symlink(fs::read_link(path)?)
+		},
+		Some(m) => Original::File(fs::read(path)?, m.permissions())
+	};
+
+	entries.push(Entry { path: path.into(), original });
+	Ok(())
+}
+
+/// Every file or symlink below `dir`.
+fn files_below(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+	for entry in fs::read_dir(dir)? {
+		let entry = entry?;
+		if entry.file_type()?.is_dir() {
+			files_below(&entry.path(), files)?;
+		} else {
+			files.push(entry.path());
+		}
+	}
+
+	Ok(())
+}
+
+/// Ends the active transaction, restoring every recorded file if the
+/// task failed.
+pub(crate) fn end(success: bool) -> RhaiResult<()> {
+	let entries = match JOURNAL.lock().unwrap().take() {
+		Some(e) => e,
+		None => return Ok(())
+	};
+
+	if success {
+		paint_ok!("commit transaction");
+		return Ok(())
+	}
+
+	paint_err!("rolling back {} paths", entries.len());
+	let mut failed = vec![];
+	for entry in entries.iter().rev() {
+		if let Err(e) = restore(entry) {
+			paint_err!("could not restore {:?}: {}", entry.path, e);
+			failed.push(format!("{:?}", entry.path));
+		}
+	}
+
+	if failed.is_empty() {
+		Ok(())
+	} else {
+		Err(err!("rollback failed for {}", failed.join(", ")))
+	}
+}
+
+fn restore(entry: &Entry) -> io::Result<()> {
+	let path = &entry.path;
+	match fs::symlink_metadata(path) {
+		Ok(m) if m.is_dir() => fs::remove_dir_all(path)?,
+		Ok(_) => fs::remove_file(path)?,
+		Err(_) => {}
+	}
+
+	if let Some(parent) = path.parent() {
+		if !matches!(entry.original, Original::Missing) {
+			fs::create_dir_all(parent)?;
+		}
+	}
+
+	match &entry.original {
+		Original::Missing => Ok(()),
+		Original::File(bytes, perms) => {
+			fs::write(path, bytes)?;
+			fs::set_permissions(path, perms.clone())
+		},
+		Original::Symlink(target) => symlink(target, path)
+	}
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+	std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+	std::os::windows::fs::symlink_file(target, link)
+}
@@ -0,0 +1,257 @@
+use super::{Filter, opt_bool};
+use crate::api::RhaiResult;
+
+use std::path::{Path, PathBuf, Component};
+use std::sync::Arc;
+
+use rhai::{Array, Map, ImmutableString};
+use globset::GlobBuilder;
+use ignore::WalkBuilder;
+
+struct WalkOpts {
+	files: bool,
+	dirs: bool,
+	max_depth: Option<usize>,
+	/// include files and directories starting with a dot
+	hidden: bool,
+	/// skip everything ignored by .gitignore files
+	gitignore: bool,
+	filter: Filter
+}
+
+impl WalkOpts {
+	fn from_map(opts: &Map) -> RhaiResult<Self> {
+		let max_depth = match opts.get("max_depth") {
+			Some(d) => {
+				let d = d.as_int()
+					.map_err(|t| err!("max_depth expected int found {}", t))?;
+				Some(d.max(0) as usize)
+			},
+			None => None
+		};
+
+		Ok(Self {
+			files: opt_bool(opts, "files", true)?,
+			dirs: opt_bool(opts, "dirs", true)?,
+			max_depth,
+			hidden: opt_bool(opts, "hidden", false)?,
+			gitignore: opt_bool(opts, "gitignore", false)?,
+			filter: Filter::from_opts(opts)?
+		})
+	}
+}
+
+pub fn walk(dir: &str) -> RhaiResult<Array> {
+	walk_with(dir, Map::new())
+}
+
+/// Returns every path below `dir` relative to it, sorted by name.
+///
+/// Options:
+/// - `files`, `dirs`: include files or directories (default true)
+/// - `max_depth`: 1 only returns the direct children
+/// - `hidden`: include entries starting with a dot (default false)
+/// - `gitignore`: skip entries ignored by .gitignore files (default false)
+/// - `include`, `exclude`: globs matched against the relative path, an
+///   excluded directory is skipped entirely
+pub fn walk_with(dir: &str, opts: Map) -> RhaiResult<Array> {
+	let opts = WalkOpts::from_map(&opts)?;
+	let root = Path::new(dir);
+	if !root.is_dir() {
+		return Err(err!("walk: {:?} is not a directory", dir))
+	}
+
+	let mut paths = Array::new();
+	walk_paths(root, &opts, |rel, is_dir| {
+		let rel = path_string(rel)?;
+		if (is_dir && opts.dirs) || (!is_dir && opts.files) {
+			paths.push(ImmutableString::from(rel).into());
+		}
+		Ok(())
+	})?;
+
+	Ok(paths)
+}
+
+/// Returns the relative path of every file below `root`, sorted by name,
+/// including hidden ones.
+pub(super) fn all_files(root: &Path) -> RhaiResult<Vec<PathBuf>> {
+	let opts = WalkOpts {
+		files: true,
+		dirs: false,
+		max_depth: None,
+		hidden: true,
+		gitignore: false,
+		filter: Filter::default()
+	};
+
+	let mut files = vec![];
+	walk_paths(root, &opts, |rel, is_dir| {
+		if !is_dir {
+			files.push(rel.to_path_buf());
+		}
+		Ok(())
+	})?;
+
+	Ok(files)
+}
+
+/// Returns the relative path of every entry below `root` which passes
+/// `filter` and whether it is a directory, sorted by name and including
+/// hidden ones.
+pub(crate) fn entries(
+	root: &Path,
+	filter: Filter
+) -> RhaiResult<Vec<(PathBuf, bool)>> {
+	let opts = WalkOpts {
+		files: true,
+		dirs: true,
+		max_depth: None,
+		hidden: true,
+		gitignore: false,
+		filter
+	};
+
+	let mut entries = vec![];
+	walk_paths(root, &opts, |rel, is_dir| {
+		entries.push((rel.to_path_buf(), is_dir));
+		Ok(())
+	})?;
+
+	Ok(entries)
+}
+
+/// Calls `f` with the relative path of every entry which passes the
+/// filter and whether it is a directory.
+fn walk_paths(
+	root: &Path,
+	opts: &WalkOpts,
+	mut f: impl FnMut(&Path, bool) -> RhaiResult<()>
+) -> RhaiResult<()> {
+	let filter = Arc::new(Filter {
+		include: None,
+		exclude: opts.filter.exclude.clone()
+	});
+	let prefix = root.to_path_buf();
+
+	let walker = WalkBuilder::new(root)
+		.max_depth(opts.max_depth)
+		.hidden(!opts.hidden)
+		.git_ignore(opts.gitignore)
+		.git_exclude(opts.gitignore)
+		.git_global(false)
+		.parents(opts.gitignore)
+		.require_git(false)
+		.ignore(false)
+		.sort_by_file_name(|a, b| a.cmp(b))
+		.filter_entry(move |e| {
+			// prune excluded directories
+			e.path().strip_prefix(&prefix)
+				.map(|rel| !filter.is_excluded(rel))
+				.unwrap_or(true)
+		})
+		.build();
+
+	for entry in walker {
+		let entry = entry.map_err(|e| err!("walk: {}", e))?;
+		if entry.depth() == 0 {
+			continue
+		}
+
+		let rel = entry.path().strip_prefix(root)
+			.map_err(|_| err!("walk: {:?} not in {:?}", entry.path(), root))?;
+		let is_dir = entry.file_type()
+			.map(|t| t.is_dir())
+			.unwrap_or(false);
+
+		if opts.filter.matches(rel) {
+			f(rel, is_dir)?;
+		}
+	}
+
+	Ok(())
+}
+
+/// Returns a path with `/` as separator.
+pub(crate) fn path_string(path: &Path) -> RhaiResult<String> {
+	let mut s = String::new();
+	for comp in path.components() {
+		let part = comp.as_os_str().to_str()
+			.ok_or_else(|| err!("invalid utf8 in {:?}", path))?;
+		match comp {
+			Component::Prefix(_) => s.push_str(part),
+			Component::RootDir => s.push('/'),
+			_ => {
+				if !s.is_empty() && !s.ends_with('/') {
+					s.push('/');
+				}
+				s.push_str(part);
+			}
+		}
+	}
+
+	Ok(s)
+}
+
+/// Returns every path matching `pattern`, sorted by name.
+///
+/// `*` does not match `/` but `**` matches any number of directories.
+pub fn glob(pattern: &str) -> RhaiResult<Array> {
+	let glob = GlobBuilder::new(pattern)
+		.literal_separator(true)
+		.build()
+		.map_err(|e| err!("invalid glob {:?}: {}", pattern, e))?
+		.compile_matcher();
+
+	// walk from the longest prefix without a glob character
+	let path = Path::new(pattern);
+	let mut base = Path::new("").to_path_buf();
+	let mut rest = 0;
+	for comp in path.components() {
+		let is_glob = comp.as_os_str().to_str()
+			.map(|s| s.contains(['*', '?', '[', '{']))
+			.unwrap_or(false);
+		if rest > 0 || is_glob {
+			rest += 1;
+		} else {
+			base.push(comp);
+		}
+	}
+
+	if rest == 0 {
+		return Ok(if path.exists() {
+			vec![ImmutableString::from(pattern).into()]
+		} else {
+			vec![]
+		})
+	}
+
+	let walk_root = if base.as_os_str().is_empty() {
+		Path::new(".")
+	} else {
+		base.as_path()
+	};
+	if !walk_root.is_dir() {
+		return Ok(vec![])
+	}
+
+	let opts = WalkOpts {
+		files: true,
+		dirs: true,
+		max_depth: (!pattern.contains("**")).then_some(rest),
+		hidden: true,
+		gitignore: false,
+		filter: Filter::default()
+	};
+
+	let mut paths = Array::new();
+	walk_paths(walk_root, &opts, |rel, _| {
+		let full = base.join(rel);
+		if glob.is_match(&full) {
+			paths.push(ImmutableString::from(path_string(&full)?).into());
+		}
+		Ok(())
+	})?;
+
+	Ok(paths)
+}
@@ -1,5 +1,5 @@
 
-use super::{RhaiResult, io_err, git_err};
+use super::{RhaiResult, Retry, io_err, git_err};
 use crate::paint::{Style, Green, Red, Cyan};
 use crate::ref_cell::{RefCell, Ref};
 
@@ -17,7 +17,8 @@ use git2::{
 use rhai::{Engine};
 
 struct Inner {
-	repo: RefCell<Repository>,
+	// none if the clone was skipped because of a dry-run
+	repo: Option<RefCell<Repository>>,
 	path: PathBuf
 }
 
@@ -39,12 +40,20 @@ impl Git {
 	fn from_repo(path: &str, repo: Repository) -> Self {
 		Self {
 			inner: Rc::new(Inner {
-				repo: repo.into(),
+				repo: Some(repo.into()),
 				path: path.into()
 			})
 		}
 	}
 
+	fn repo(&self) -> RhaiResult<&RefCell<Repository>> {
+		self.inner.repo.as_ref()
+			.ok_or_else(|| err!(
+				"git: {:?} was not cloned because of dry-run",
+				self.inner.path
+			))
+	}
+
 	#[allow(dead_code)]
 	fn root(&self) -> PathBuf {
 		self.inner.path.clone()
@@ -58,6 +67,13 @@ impl Git {
 			return Self::new(path)
 		}
 
+		dry_run!(Ok(Self {
+			inner: Rc::new(Inner {
+				repo: None,
+				path: path.into()
+			})
+		}));
+
 		Ok(Self::from_repo(
 			path,
 			Repository::clone(url, path)
@@ -65,6 +81,30 @@ impl Git {
 		))
 	}
 
+	/// Like `clone` but retries up to `times` times, waiting `delay_ms`
+	/// before the first retry and doubling the delay after every attempt.
+	pub fn clone_retry(
+		url: &str,
+		path: &str,
+		times: i64,
+		delay_ms: i64
+	) -> RhaiResult<Self> {
+		let retry = Retry::new(times, delay_ms)?;
+		retry.run(
+			&format!("git clone {:?}", url),
+			|| {
+				let existed = Path::new(path).exists();
+				let r = Self::clone(url, path);
+				// remove what a failed clone left behind
+				if r.is_err() && !existed {
+					let _ = fs::remove_dir_all(path);
+				}
+				r
+			},
+			|_| true
+		)
+	}
+
 	// pub fn print_diff(&mut self) -> RhaiResult<()> {
 	// 	let repo = self.inner.borrow();
 	// 	let diff = repo.diff_index_to_workdir(
@@ -95,9 +135,12 @@ impl Git {
 
 	fn apply_diff<D>(&mut self, diff: D) -> RhaiResult<()>
 	where D: RawDiff {
+		paint_act!("git apply diff in {:?}", self.inner.path);
+		dry_run!(Ok(()));
+
 		let diff = diff.raw_diff()?;
-		self.inner.repo.borrow()
-			.apply(&*diff, ApplyLocation::WorkDir, None)
+		self.repo()?.borrow()
+			.apply(&diff, ApplyLocation::WorkDir, None)
 			.map_err(git_err)?;
 		Ok(())
 	}
@@ -162,7 +205,10 @@ impl Git {
 	// }
 
 	pub fn force_head(&mut self) -> RhaiResult<()> {
-		let repo = self.inner.repo.borrow();
+		paint_act!("git force head in {:?}", self.inner.path);
+		dry_run!(Ok(()));
+
+		let repo = self.repo()?.borrow();
 		let mut ops = CheckoutBuilder::new();
 		ops.force()
 			.remove_untracked(true);
@@ -174,7 +220,7 @@ impl Git {
 	fn find_tag(&mut self, tag: &str) -> RhaiResult<Oid> {
 		let tag = format!("refs/tags/{}", tag);
 		let mut id = None;
-		self.inner.repo.borrow()
+		self.repo()?.borrow()
 			.tag_foreach(|tag_id, name| {
 				let name = from_utf8(name).ok();
 				if matches!(name, Some(n) if n == tag) {
@@ -189,9 +235,11 @@ impl Git {
 
 	pub fn checkout_tag(&mut self, tag: &str) -> RhaiResult<()> {
 		paint_act!("checkout tag {:?}", tag);
+		dry_run!(Ok(()));
+
 		let tag_id = self.find_tag(tag)?;
 
-		let repo = self.inner.repo.borrow();
+		let repo = self.repo()?.borrow();
 
 		let obj = repo.find_object(tag_id, None)
 			.map_err(git_err)?;
@@ -221,7 +269,7 @@ impl Diff {
 		// Todo this is probably not the right way
 		// but it works. (help me!)
 
-		let repo = self.inner.inner.repo.borrow();
+		let repo = self.inner.repo()?.borrow();
 		Ref::transpose(unsafe {Ref::map(repo, |repo| {
 
 			let mut index = repo.index()
@@ -281,6 +329,7 @@ impl Diff {
 		Ok(())
 	}
 
+	#[allow(clippy::wrong_self_convention)]
 	fn to_string(&mut self) -> RhaiResult<String> {
 		let diff = self.compute()?;
 		let mut s = String::new();
@@ -295,10 +344,10 @@ impl Diff {
 		Ok(s)
 	}
 
+	#[allow(clippy::wrong_self_convention)]
 	fn to_file(&mut self, file: &str) -> RhaiResult<()> {
 		let s = self.to_string()?;
-		fs::write(file, s)
-			.map_err(io_err)
+		super::fs::write_file_str(file, &s)
 	}
 
 	// /// the first is the origin file
@@ -390,7 +439,7 @@ impl<'a, T> ops::Deref for MaybeRef<'a, T> {
 	type Target = T;
 	fn deref(&self) -> &T {
 		match self {
-			Self::Has(r) => &*r,
+			Self::Has(r) => r,
 			// Self::No(t) => &t,
 			Self::Borrowed(t) => t
 		}
@@ -426,6 +475,7 @@ pub fn add(engine: &mut Engine) {
 	engine
 		.register_fn("git", Git::new)
 		.register_fn("git_clone", Git::clone)
+		.register_fn("git_clone", Git::clone_retry)
 		.register_fn("diff", Git::diff)
 		.register_fn("apply_diff", Git::apply_diff::<DiffInFile>)
 		.register_fn("apply_diff", Git::apply_diff::<Diff>)
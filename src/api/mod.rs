@@ -1,7 +1,9 @@
 
 use crate::script::RhaiResult;
 
-use std::io;
+use std::{io, thread};
+use std::time::Duration;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 pub type RhaiError = Box<rhai::EvalAltResult>;
 
@@ -14,6 +16,16 @@ macro_rules! err {
 	)
 }
 
+/// Returns `$ret` early if riji runs with `--dry-run`. The action should
+/// be printed before.
+macro_rules! dry_run {
+	($ret:expr) => (
+		if $crate::api::is_dry_run() {
+			return $ret
+		}
+	)
+}
+
 pub mod cmd;
 pub mod git;
 pub mod fs;
@@ -21,6 +33,69 @@ pub mod regex;
 pub mod other;
 pub mod toml;
 pub mod util;
+pub mod archive;
+
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+/// If enabled every side-effecting api only prints its action.
+pub fn set_dry_run(dry_run: bool) {
+	DRY_RUN.store(dry_run, Ordering::Relaxed);
+}
+
+pub fn is_dry_run() -> bool {
+	DRY_RUN.load(Ordering::Relaxed)
+}
+
+/// How often a failing action is repeated.
+#[derive(Debug, Clone, Copy, Default)]
+struct Retry {
+	times: u32,
+	/// in milliseconds, doubles after every attempt
+	delay: u64
+}
+
+impl Retry {
+	fn new(times: i64, delay_ms: i64) -> RhaiResult<Self> {
+		if times < 0 || delay_ms < 0 {
+			return Err(err!("retry: times and delay cannot be negative"))
+		}
+
+		Ok(Self {
+			times: times.try_into().unwrap_or(u32::MAX),
+			delay: delay_ms as u64
+		})
+	}
+
+	/// Calls `f` until `done` returns true for its result or the retries
+	/// are exhausted. Errors are also retried.
+	///
+	/// The last result is returned.
+	fn run<T>(
+		&self,
+		what: &str,
+		mut f: impl FnMut() -> RhaiResult<T>,
+		done: impl Fn(&T) -> bool
+	) -> RhaiResult<T> {
+		let attempts = self.times.saturating_add(1);
+		let mut delay = self.delay;
+		let mut attempt = 1;
+		loop {
+			let r = f();
+			let ok = r.as_ref().map(&done).unwrap_or(false);
+			if ok || attempt >= attempts {
+				return r
+			}
+
+			paint_act!(
+				"{} failed (attempt {}/{}), retrying in {}ms",
+				what, attempt, attempts, delay
+			);
+			thread::sleep(Duration::from_millis(delay));
+			delay = delay.saturating_mul(2);
+			attempt += 1;
+		}
+	}
+}
 
 fn git_err(e: git2::Error) -> RhaiError {
 	err!("{:?}", e)
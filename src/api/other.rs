@@ -1,5 +1,7 @@
 
-use super::RhaiResult;
+use super::{RhaiResult, io_err};
+
+use std::{env, fs};
 
 use uuid::Uuid;
 
@@ -16,13 +18,112 @@ fn env_var(key: &str) -> String {
 	std::env::var(key).unwrap_or_else(|_| String::new())
 }
 
+/// Sets an environment variable for the script and every command started
+/// afterwards.
+fn set_env(key: &str, val: &str) -> RhaiResult<()> {
+	check_env_key(key)?;
+	check_env_val(key, val)?;
+	env::set_var(key, val);
+	Ok(())
+}
+
+fn unset_env(key: &str) -> RhaiResult<()> {
+	check_env_key(key)?;
+	env::remove_var(key);
+	Ok(())
+}
+
+fn check_env_key(key: &str) -> RhaiResult<()> {
+	if key.is_empty() || key.contains(['=', '\0']) {
+		Err(err!("invalid environment variable name {:?}", key))
+	} else {
+		Ok(())
+	}
+}
+
+/// env::set_var panics on a value containing a nul byte.
+fn check_env_val(key: &str, val: &str) -> RhaiResult<()> {
+	if val.contains('\0') {
+		Err(err!("invalid value for environment variable {:?}", key))
+	} else {
+		Ok(())
+	}
+}
+
+/// Loads the variables of a `.env` file into the environment, variables
+/// which are already set are not overwritten.
+///
+/// Supports comments, `export KEY=val` and single or double quoted values.
+fn dotenv(path: &str) -> RhaiResult<()> {
+	let s = fs::read_to_string(path)
+		.map_err(io_err)?;
+
+	for (i, line) in s.lines().enumerate() {
+		let line = line.trim();
+		if line.is_empty() || line.starts_with('#') {
+			continue
+		}
+
+		let (key, val) = parse_env_line(line)
+			.ok_or_else(|| err!("dotenv: invalid line {} in {:?}", i + 1, path))?;
+		check_env_key(key)?;
+		check_env_val(key, &val)?;
+
+		if env::var_os(key).is_none() {
+			env::set_var(key, val);
+		}
+	}
+
+	Ok(())
+}
+
+fn parse_env_line(line: &str) -> Option<(&str, String)> {
+	let line = line.strip_prefix("export ").unwrap_or(line);
+	let (key, val) = line.split_once('=')?;
+	let key = key.trim();
+	let val = val.trim();
+
+	let val = if let Some(v) = val.strip_prefix('"') {
+		unescape_until_quote(v)?
+	} else if let Some(v) = val.strip_prefix('\'') {
+		let (v, _) = v.split_once('\'')?;
+		v.into()
+	} else {
+		// strip a comment at the end
+		match val.find(" #") {
+			Some(i) => val[..i].trim_end().into(),
+			None => val.into()
+		}
+	};
+
+	Some((key, val))
+}
+
+/// Returns the content of a double quoted value, `s` starts after the
+/// opening quote.
+fn unescape_until_quote(s: &str) -> Option<String> {
+	let mut out = String::new();
+	let mut chars = s.chars();
+	loop {
+		match chars.next()? {
+			'"' => return Some(out),
+			'\\' => match chars.next()? {
+				'n' => out.push('\n'),
+				't' => out.push('\t'),
+				c => out.push(c)
+			},
+			c => out.push(c)
+		}
+	}
+}
+
 fn env_os() -> &'static str {
 	if cfg!(target_os = "windows") {
-		return "windows";
+		"windows"
 	} else if cfg!(target_os = "linux") {
-		return "linux";
+		"linux"
 	} else {
-		return "unknown";
+		"unknown"
 	}
 }
 
@@ -68,6 +169,9 @@ pub fn add(engine: &mut Engine) {
 	engine
 		.register_fn("uuid_v4", uuid_v4)
 		.register_fn("env_var", env_var)
+		.register_fn("set_env", set_env)
+		.register_fn("unset_env", unset_env)
+		.register_fn("dotenv", dotenv)
 		.register_fn("trim", trim)
 		.register_fn("lowercase", lowercase)
 		.register_fn("uppercase", uppercase)
@@ -75,5 +179,6 @@ pub fn add(engine: &mut Engine) {
 		.register_fn("ends_with", ends_with)
 		.register_fn("sort_strs", sort_strs)
 		.register_fn("env_os", env_os)
+		.register_fn("is_dry_run", super::is_dry_run)
 		.register_fn("sleep", sleep);
 }
\ No newline at end of file
@@ -22,11 +22,12 @@ impl Regex {
 		self.inner.is_match(s)
 	}
 
+	/// Invalid utf8 in the file is ignored.
 	pub fn matches_file(&mut self, path: &str) -> RhaiResult<bool> {
-		let s = fs::read_to_string(path)
+		let bytes = fs::read(path)
 			.map_err(io_err)?;
 
-		Ok(self.inner.is_match(&s))
+		Ok(self.inner.is_match(&String::from_utf8_lossy(&bytes)))
 	}
 
 	pub fn find(&mut self, s: &str) -> ImmutableString {
@@ -78,9 +79,10 @@ impl Regex {
 			// nothing to replace
 			Cow::Borrowed(_) => Ok(()),
 			Cow::Owned(s) => {
-				fs::write(path, s)
+				paint_act!("replace in {:?}", path);
+				dry_run!(Ok(()));
+				super::fs::write_atomic(path, s.as_bytes())
 					.map_err(io_err)
-					.map(|_| ())
 			}
 		}
 	}
@@ -13,7 +13,7 @@ fn read(path: &str) -> RhaiResult<Dynamic> {
 }
 
 fn parse(ctn: &str) -> RhaiResult<Dynamic> {
-	from_str(&ctn)
+	from_str(ctn)
 		.map_err(|e| err!("toml: failed to deserialize error {:?}", e))
 }
 
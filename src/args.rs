@@ -0,0 +1,95 @@
+use rhai::{Dynamic, Map, ScriptFnMetadata};
+
+/// The name of the parameter which receives the `--options`.
+pub const OPTS_PARAM: &str = "opts";
+
+/// Command line arguments split into positional arguments and options.
+///
+/// `--flag` is stored as `true` and `--key=value` as a string. Everything
+/// after a lone `--` is treated as positional.
+#[derive(Debug, Clone, Default)]
+pub struct Args {
+	pub positional: Vec<String>,
+	pub options: Map
+}
+
+impl Args {
+	pub fn parse(args: Vec<String>) -> Self {
+		let mut me = Self::default();
+		let mut args = args.into_iter();
+
+		while let Some(arg) = args.next() {
+			if arg == "--" {
+				me.positional.extend(args.by_ref());
+				break
+			}
+
+			let opt = match arg.strip_prefix("--") {
+				Some(opt) => opt,
+				None => {
+					me.positional.push(arg);
+					continue
+				}
+			};
+
+			match opt.split_once('=') {
+				Some((key, val)) => {
+					me.options.insert(key.into(), val.into());
+				},
+				None => {
+					me.options.insert(opt.into(), true.into());
+				}
+			}
+		}
+
+		me
+	}
+
+	/// Returns the arguments to call `f` with if they match its parameters.
+	pub fn fn_args(&self, f: &ScriptFnMetadata) -> Option<Vec<Dynamic>> {
+		let takes_opts = takes_opts(f);
+		let required = f.params.len() - takes_opts as usize;
+
+		if self.positional.len() != required ||
+			(!takes_opts && !self.options.is_empty())
+		{
+			return None
+		}
+
+		let mut args: Vec<Dynamic> = self.positional.iter()
+			.map(|a| a.clone().into())
+			.collect();
+		if takes_opts {
+			args.push(self.options.clone().into());
+		}
+
+		Some(args)
+	}
+}
+
+fn takes_opts(f: &ScriptFnMetadata) -> bool {
+	f.params.last().map(|p| *p == OPTS_PARAM).unwrap_or(false)
+}
+
+/// Returns the usage line of a script function for example
+/// `build <target> [--options]`.
+pub fn usage(f: &ScriptFnMetadata) -> String {
+	let mut s = f.name.to_string();
+	let params = if takes_opts(f) {
+		&f.params[..f.params.len() - 1]
+	} else {
+		&f.params[..]
+	};
+
+	for param in params {
+		s.push_str(" <");
+		s.push_str(param);
+		s.push('>');
+	}
+
+	if takes_opts(f) {
+		s.push_str(" [--options]");
+	}
+
+	s
+}
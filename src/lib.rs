@@ -4,8 +4,12 @@
 pub mod paint;
 
 mod script;
+mod args;
+mod tasks;
 pub use script::{Script, Result, Error};
 
 mod api;
+pub use api::set_dry_run;
+pub use api::fs::{set_keep_temp, cleanup_temp};
 
 mod ref_cell;
\ No newline at end of file
@@ -13,39 +13,57 @@ features:
 - custom commands ?
 */
 
-use std::env;
+use std::{env, fs};
 
-use std::path::Path;
+use std::io;
+use std::path::{Path, PathBuf};
 
-use riji::{Script, Result, Error, paint::Red, epaintln};
+use riji::{Script, Result, Error, paint_act};
 
 fn main() {
-	if let Err(e) = execute() {
-		match e {
-			Error::Rhai(e) => {
-				epaintln!(Red, "rhai error {:?}", e)
-			},
-			Error::Parse(e) => {
-				epaintln!(Red, "parse error {:?}", e)
-			},
-			Error::Io(_) => {
-				epaintln!(Red, "file \"./riji.rhai\" not found")
-				// epaintln!(Red, "io error {:?}", e)
-			},
-		}
+	let mut file = None;
+	let r = execute(&mut file);
+	riji::cleanup_temp();
+
+	if let Err(e) = r {
+		let file = file.unwrap_or_else(|| "./riji.rhai".into());
+		let src = fs::read_to_string(&file).ok();
+		eprint!("{}", e.render(&file, src.as_deref()));
 		// return with error
 		std::process::exit(1);
 	}
 }
 
-fn execute() -> Result<()> {
-	let mut args = std::env::args()
-		.skip(1);
-	let cmd = args.next()
-		.unwrap_or("help".into());
-	let args: Vec<_> = args.collect();
+/// `script_file` is set to the script path once it is known.
+fn execute(script_file: &mut Option<String>) -> Result<()> {
+	let mut args: Vec<_> = std::env::args()
+		.skip(1)
+		.collect();
+
+	// global flags are accepted anywhere before `--`, which separates the
+	// commands from their arguments
+	let mut after_sep = false;
+	args.retain(|arg| {
+		if after_sep {
+			return true
+		}
+		match arg.as_str() {
+			"--" => after_sep = true,
+			"--dry-run" => {
+				riji::set_dry_run(true);
+				paint_act!("dry-run: no changes will be made");
+				return false
+			},
+			"--keep-temp" => {
+				riji::set_keep_temp(true);
+				return false
+			},
+			_ => {}
+		}
+		true
+	});
 
-	if cmd == "--version" {
+	if args.first().map(|a| a == "--version").unwrap_or(false) {
 		println!("Riji version {}", env!("CARGO_PKG_VERSION"));
 		return Ok(())
 	}
@@ -63,10 +81,33 @@ fn execute() -> Result<()> {
 			.expect("failed to get file_name form RIJI_SCRIPT");
 
 		let path = format!("./{}", file_name.to_str().unwrap());
+		*script_file = Some(path.clone());
 		Script::new(path)?
 	} else {
+		let root = find_root()?;
+		env::set_current_dir(&root)
+			.map_err(Error::Io)?;
+
+		*script_file = Some("./riji.rhai".into());
 		Script::new("./riji.rhai")?
 	};
 
-	script.execute(&cmd, args)
-}
\ No newline at end of file
+	script.execute_all(args)
+}
+
+/// Returns the nearest directory, starting from the current one, which
+/// contains a riji.rhai file.
+fn find_root() -> Result<PathBuf> {
+	let cwd = env::current_dir()?;
+
+	cwd.ancestors()
+		.find(|dir| dir.join("riji.rhai").is_file())
+		.map(Into::into)
+		.ok_or_else(|| io::Error::new(
+			io::ErrorKind::NotFound,
+			format!(
+				"could not find \"riji.rhai\" in {:?} or any parent directory",
+				cwd
+			)
+		).into())
+}
@@ -123,7 +123,7 @@ impl<T: ?Sized> RefCell<T> {
 
 	/// ## Panics
 	/// If the value is already mutably borrowed.
-	pub fn leak_ref<'a>(&'a self) -> &'a T {
+	pub fn leak_ref(&self) -> &T {
 		self.increment_ref();
 
 		// safe because increment_ref checked
@@ -144,6 +144,7 @@ impl<T: ?Sized> RefCell<T> {
 		}
 	}
 
+	#[allow(clippy::mut_from_ref)]
 	pub fn borrow_mut(&self) -> Ref<'_, &mut T> {
 		self.increment_ref_mut();
 
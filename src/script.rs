@@ -1,13 +1,21 @@
 
-use std::io;
-use std::path::Path;
+use std::{io, env};
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
 use std::fs::read_to_string;
 
 use rhai::{
 	Engine, Array, Scope, AST, FuncArgs, EvalAltResult, ParseError,
-	CallFnOptions
+	CallFnOptions, Dynamic, ScriptFnMetadata, Position, Stmt, Expr
 };
 use rhai::packages::{StandardPackage, Package};
+use rhai::module_resolvers::{FileModuleResolver, ModuleResolversCollection};
+
+use crate::args::{self, Args};
+use crate::tasks::Tasks;
+use crate::api::fs::end_transaction;
+use crate::paint::{Red, Blue, Style};
 
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -18,7 +26,11 @@ pub type RhaiResult<T> = std::result::Result<T, Box<EvalAltResult>>;
 pub enum Error {
 	Rhai(Box<EvalAltResult>),
 	Parse(ParseError),
-	Io(io::Error)
+	Io(io::Error),
+	/// the arguments did not match the parameters of the command
+	Usage(String),
+	/// the task dependencies contain a cycle
+	Cycle(Vec<String>)
 }
 
 impl From<Box<EvalAltResult>> for Error {
@@ -39,23 +51,184 @@ impl From<io::Error> for Error {
 	}
 }
 
+impl Error {
+	/// Renders the error as a diagnostic containing the file name,
+	/// the line and column, the offending source line and for rhai errors
+	/// the call stack.
+	///
+	/// `src` should be the content of `file`.
+	pub fn render(&self, file: &str, src: Option<&str>) -> String {
+		let mut s = String::new();
+
+		match self {
+			Self::Rhai(e) => {
+				let mut stack = vec![];
+				// the source the current position refers to
+				// empty means `file`
+				let mut cur_src = "";
+				// source and position of the innermost function call
+				let mut call_site = None;
+				let mut e: &EvalAltResult = e;
+
+				loop {
+					match e {
+						EvalAltResult::ErrorInFunctionCall(name, fn_src, inner, pos) => {
+							stack.push(format!(
+								"in call to function '{}' at {}",
+								name, location(file, cur_src, *pos)
+							));
+							if !pos.is_none() {
+								call_site = Some((cur_src, *pos));
+							}
+							cur_src = fn_src;
+							e = inner;
+						},
+						EvalAltResult::ErrorInModule(name, inner, pos) => {
+							stack.push(format!(
+								"in module '{}' at {}",
+								name, location(file, cur_src, *pos)
+							));
+							cur_src = name;
+							e = inner;
+						},
+						_ => break
+					}
+				}
+
+				let pos = e.position();
+				let msg = e.to_string();
+				let pos_suffix = format!(" ({})", pos);
+				let msg = msg.strip_suffix(&pos_suffix).unwrap_or(&msg);
+
+				header(&mut s, msg);
+				// errors from the native api have no position, point at
+				// the function call instead
+				let (cur_src, pos) = match call_site {
+					Some(site) if pos.is_none() => site,
+					_ => (cur_src, pos)
+				};
+				let src = if cur_src.is_empty() { src } else { None };
+				snippet(&mut s, file, cur_src, src, pos);
+
+				// the innermost call first
+				for frame in stack.iter().rev() {
+					s.push_str(&format!("   {} {}\n", Blue.paint("="), frame));
+				}
+			},
+			Self::Parse(e) => {
+				header(&mut s, &e.err_type().to_string());
+				snippet(&mut s, file, "", src, e.position());
+			},
+			Self::Io(e) => header(&mut s, &e.to_string()),
+			Self::Usage(u) => {
+				header(&mut s, "invalid arguments");
+				for line in u.lines() {
+					s.push_str(&format!("  usage: {}\n", line));
+				}
+			},
+			Self::Cycle(c) => {
+				header(&mut s, &format!(
+					"dependency cycle detected: {}",
+					c.join(" -> ")
+				));
+			}
+		}
+
+		s
+	}
+}
+
+fn header(s: &mut String, msg: &str) {
+	s.push_str(&format!(
+		"{}{}\n",
+		Red.bold().paint("error: "),
+		Style::new().bold().paint(msg)
+	));
+}
+
+fn location(file: &str, src: &str, pos: Position) -> String {
+	let file = if src.is_empty() { file } else { src };
+	match (pos.line(), pos.position()) {
+		(Some(line), Some(col)) => format!("{}:{}:{}", file, line, col),
+		(Some(line), None) => format!("{}:{}", file, line),
+		_ => file.to_string()
+	}
+}
+
+/// Writes the location and if available the source line with a caret
+/// pointing at the column.
+fn snippet(
+	s: &mut String,
+	file: &str,
+	cur_src: &str,
+	src: Option<&str>,
+	pos: Position
+) {
+	s.push_str(&format!(
+		"  {} {}\n",
+		Blue.paint("-->"),
+		location(file, cur_src, pos)
+	));
+
+	let line_nr = match pos.line() {
+		Some(l) => l,
+		None => return
+	};
+	let line = match src.and_then(|src| src.lines().nth(line_nr - 1)) {
+		Some(l) => l,
+		None => return
+	};
+
+	let nr = line_nr.to_string();
+	let pad = " ".repeat(nr.len());
+	let col = pos.position().unwrap_or(1);
+	// keep tabs so the caret lines up with the source line
+	let indent: String = line.chars()
+		.take(col.saturating_sub(1))
+		.map(|c| if c == '\t' { '\t' } else { ' ' })
+		.collect();
+
+	s.push_str(&format!("{} {}\n", pad, Blue.paint("|")));
+	s.push_str(&format!("{} {} {}\n", Blue.paint(&nr), Blue.paint("|"), line));
+	s.push_str(&format!(
+		"{} {} {}{}\n",
+		pad,
+		Blue.paint("|"),
+		indent,
+		Red.bold().paint("^")
+	));
+}
+
 #[derive(Debug)]
 pub struct Script {
 	engine: Engine,
 	scope: Scope<'static>,
-	ast: AST
+	ast: AST,
+	tasks: Rc<RefCell<Tasks>>
 }
 
 impl Script {
 	pub fn new(p: impl AsRef<Path>) -> Result<Self> {
-		let engine = new_engine();
+		let p = p.as_ref();
+		let root = p.parent()
+			.filter(|p| !p.as_os_str().is_empty())
+			.unwrap_or(Path::new("."));
+		let root = dunce::canonicalize(root)?;
+		let mut engine = new_engine(root);
+		let tasks = Rc::new(RefCell::new(Tasks::default()));
+		register_tasks(&mut engine, tasks.clone());
 		let mut scope = Scope::new();
-		let ctn = read_to_string(p)?;
+		let ctn = read_to_string(p)
+			.map_err(|e| io::Error::new(
+				e.kind(),
+				format!("could not read {:?}: {}", p, e)
+			))?;
 		let ast = engine.compile_with_scope(&scope, &ctn)?;
+		register_imports(&mut engine, &ast)?;
 		engine.eval_ast_with_scope(&mut scope, &ast)?;
 		//ast.clear_statements();
 
-		Ok(Self { engine, scope, ast })
+		Ok(Self { engine, scope, ast, tasks })
 	}
 
 	fn call_fn(
@@ -63,7 +236,7 @@ impl Script {
 		name: &str,
 		args: impl FuncArgs
 	) -> Result<()> {
-		let _ = self.engine.call_fn_with_options(
+		let _: Dynamic = self.engine.call_fn_with_options(
 			CallFnOptions::new()
 				.eval_ast(false)
 				.rewind_scope(false),
@@ -76,19 +249,162 @@ impl Script {
 		Ok(())
 	}
 
+	fn has_fn(&self, name: &str) -> bool {
+		self.ast.iter_functions()
+			.any(|f| f.name == name)
+	}
+
+	/// Returns a help text listing every public script function
+	/// with its parameters and doc comments.
+	pub fn help(&self) -> String {
+		let mut fns: Vec<_> = self.ast.iter_functions()
+			.filter(|f| f.access.is_public())
+			.collect();
+		fns.sort_by(|a, b| a.name.cmp(b.name));
+
+		let mut s = String::from("Usage: riji <command>... [--] [args...]\n\nCommands:\n");
+		for f in fns {
+			s.push_str("  ");
+			s.push_str(&args::usage(&f));
+			s.push('\n');
+
+			for line in doc_lines(&f) {
+				s.push_str("      ");
+				s.push_str(line);
+				s.push('\n');
+			}
+		}
+
+		s
+	}
+
+	/// Executes one or more commands in sequence, stopping at the first
+	/// failure.
+	///
+	/// `riji clone patch build` runs every leading entry which is a script
+	/// function, the remaining entries are passed as arguments to the last
+	/// command. Use `--` to separate the commands from the arguments
+	/// explicitly: `riji clone build -- linux`.
+	pub fn execute_all(&mut self, mut argv: Vec<String>) -> Result<()> {
+		let args = match argv.iter().position(|a| a == "--") {
+			Some(i) => {
+				let args = argv.split_off(i + 1);
+				argv.pop();
+				args
+			},
+			None => {
+				// the first entry is always a command
+				let cmds = 1 + argv.iter()
+					.skip(1)
+					.take_while(|a| self.has_fn(a))
+					.count();
+				argv.split_off(cmds.min(argv.len()))
+			}
+		};
+
+		let last = match argv.pop() {
+			Some(l) => l,
+			None => return self.execute("help", args)
+		};
+
+		for cmd in argv {
+			self.execute(&cmd, vec![])?;
+		}
+
+		self.execute(&last, args)
+	}
+
 	pub fn execute(
 		&mut self,
 		cmd: &str,
 		args: Vec<String>
 	) -> Result<()> {
-		self.call_fn(cmd, args)
+		// a user defined help function takes precedence
+		if cmd == "help" && !self.has_fn("help") {
+			print!("{}", self.help());
+			return Ok(())
+		}
+
+		let args = Args::parse(args);
+
+		let mut usages = vec![];
+		let mut fn_args = None;
+		for f in self.ast.iter_functions().filter(|f| f.name == cmd) {
+			match args.fn_args(&f) {
+				Some(a) => {
+					fn_args = Some(a);
+					break
+				},
+				None => usages.push(format!("riji {}", args::usage(&f)))
+			}
+		}
+
+		let fn_args = match fn_args {
+			Some(a) => a,
+			// let rhai report that the function does not exist
+			None if usages.is_empty() => {
+				return self.call_fn(cmd, args.positional)
+			},
+			None => return Err(Error::Usage(usages.join("\n")))
+		};
+
+		let plan = self.tasks.borrow().plan(cmd)?;
+		if plan.len() > 1 {
+			paint_act!("plan: {}", plan.join(" -> "));
+		}
+
+		for task in &plan[..plan.len() - 1] {
+			let r = self.call_fn(task, ());
+			// the task error is more important than a failed rollback
+			let ended = end_transaction(r.is_ok());
+			r?;
+			ended?;
+			self.tasks.borrow_mut().mark_done(task);
+		}
+
+		let r = self.call_fn(cmd, fn_args);
+		let ended = end_transaction(r.is_ok());
+		r?;
+		ended?;
+		self.tasks.borrow_mut().mark_done(cmd);
+
+		Ok(())
 	}
 }
 
+/// Returns the doc comment lines without the comment markers.
+fn doc_lines<'a>(f: &ScriptFnMetadata<'a>) -> Vec<&'a str> {
+	f.comments.iter()
+		.flat_map(|c| c.lines())
+		.map(|l| {
+			let l = l.trim();
+			let l = l.strip_prefix("///")
+				.or_else(|| l.strip_prefix("/**"))
+				.unwrap_or(l);
+			let l = l.strip_suffix("*/").unwrap_or(l);
+			let l = l.strip_prefix('*').unwrap_or(l);
+			l.trim()
+		})
+		.filter(|l| !l.is_empty())
+		.collect()
+}
+
 
-fn new_engine() -> Engine {
+fn new_engine(root: PathBuf) -> Engine {
 	let mut engine = Engine::new_raw();
 
+	// imports are first resolved relative to the script and then relative
+	// to the shared library directory RIJI_LIB
+	// both resolvers cache compiled modules for the run
+	let mut resolvers = ModuleResolversCollection::new();
+	resolvers.push(FileModuleResolver::new_with_path(&root));
+	if let Ok(lib) = env::var("RIJI_LIB") {
+		resolvers.push(FileModuleResolver::new_with_path(root.join(lib)));
+	}
+	engine.set_module_resolver(resolvers);
+
+	let root = root.to_string_lossy().into_owned();
+
 	engine.register_global_module(StandardPackage::new().as_shared_module());
 	engine.set_fail_on_invalid_map_property(true);
 
@@ -102,6 +418,8 @@ fn new_engine() -> Engine {
 		.register_fn("print", print_bool)
 		.register_fn("print", print_arr)
 		.register_fn("prompt", prompt)
+		// the directory containing the script
+		.register_fn("riji_root", move || root.clone())
 		.register_fn("panic", panic);
 
 	crate::api::cmd::add(&mut engine);
@@ -111,10 +429,47 @@ fn new_engine() -> Engine {
 	crate::api::other::add(&mut engine);
 	crate::api::toml::add(&mut engine);
 	crate::api::util::add(&mut engine);
+	crate::api::archive::add(&mut engine);
 
 	engine
 }
 
+/// Registers `depends(task, [deps])` which declares that `deps` need
+/// to run before `task`.
+fn register_tasks(engine: &mut Engine, tasks: Rc<RefCell<Tasks>>) {
+	engine.register_fn("depends", move |task: &str, deps: Array| {
+		let deps = deps.into_iter()
+			.map(|d| d.into_string())
+			.collect::<std::result::Result<_, _>>()
+			.map_err(|e| format!("depends: expected strings found {}", e))?;
+		tasks.borrow_mut().depends(task, deps);
+
+		RhaiResult::Ok(())
+	});
+}
+
+/// Registers the modules imported at the top level of the script as static
+/// modules, so they can also be used inside of functions called by riji.
+fn register_imports(engine: &mut Engine, ast: &AST) -> RhaiResult<()> {
+	for stmt in ast.statements() {
+		let (path, alias) = match stmt {
+			Stmt::Import(x, _) => match &x.0 {
+				Expr::StringConstant(path, _) if !x.1.name.is_empty() => {
+					(path, &x.1.name)
+				},
+				_ => continue
+			},
+			_ => continue
+		};
+
+		let module = engine.module_resolver()
+			.resolve(engine, None, path, stmt.position())?;
+		engine.register_static_module(alias.as_str(), module);
+	}
+
+	Ok(())
+}
+
 fn print_arr(arr: Array) -> String {
 	let mut s = String::new();
 	for (i, el) in arr.into_iter().enumerate() {
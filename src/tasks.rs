@@ -0,0 +1,70 @@
+use crate::script::{Result, Error};
+
+use std::collections::{HashMap, HashSet};
+
+/// Dependencies between script functions declared with
+/// `depends("build", ["patch"])`.
+#[derive(Debug, Default)]
+pub struct Tasks {
+	deps: HashMap<String, Vec<String>>,
+	/// tasks which already ran in this invocation
+	done: HashSet<String>
+}
+
+impl Tasks {
+	pub fn depends(&mut self, task: &str, deps: Vec<String>) {
+		self.deps.entry(task.into())
+			.or_default()
+			.extend(deps);
+	}
+
+	pub fn deps(&self, task: &str) -> &[String] {
+		self.deps.get(task)
+			.map(|d| d.as_slice())
+			.unwrap_or(&[])
+	}
+
+	/// Returns all tasks that need to run before `task` in the order they
+	/// should be executed, `task` itself is the last entry.
+	///
+	/// Tasks that already ran are skipped.
+	pub fn plan(&self, task: &str) -> Result<Vec<String>> {
+		let mut plan = vec![];
+		self.visit(task, &mut vec![], &mut plan)?;
+
+		// the task itself is always executed
+		plan.retain(|t| t == task || !self.done.contains(t));
+
+		Ok(plan)
+	}
+
+	fn visit(
+		&self,
+		task: &str,
+		stack: &mut Vec<String>,
+		plan: &mut Vec<String>
+	) -> Result<()> {
+		if plan.iter().any(|t| t == task) {
+			return Ok(())
+		}
+
+		if let Some(i) = stack.iter().position(|t| t == task) {
+			let mut cycle = stack[i..].to_vec();
+			cycle.push(task.into());
+			return Err(Error::Cycle(cycle))
+		}
+
+		stack.push(task.into());
+		for dep in self.deps(task) {
+			self.visit(dep, stack, plan)?;
+		}
+		stack.pop();
+
+		plan.push(task.into());
+		Ok(())
+	}
+
+	pub fn mark_done(&mut self, task: &str) {
+		self.done.insert(task.into());
+	}
+}
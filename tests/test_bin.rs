@@ -1,4 +1,6 @@
-use std::process::Command;
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
 
 #[test]
 fn test_help() {
@@ -15,4 +17,865 @@ fn test_help() {
 
 	assert_eq!(stdout, "test help\n- help\n- test1\n");
 	assert_eq!(stderr, "");
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_auto_help() {
+	let output = Command::new("./target/debug/riji")
+		.env("RIJI_SCRIPT", "tests/test_auto_help.rhai")
+		.output()
+		.expect("Failed to execute command");
+
+	assert!(output.status.success());
+
+	let stdout = String::from_utf8(output.stdout).unwrap();
+
+	assert_eq!(stdout, "\
+Usage: riji <command>... [--] [args...]
+
+Commands:
+  build <target> <profile>
+      Builds the project
+      for the given target
+  clone
+      Clones the repository
+");
+}
+
+#[test]
+fn test_args() {
+	let output = Command::new("./target/debug/riji")
+		.env("RIJI_SCRIPT", "tests/test_args.rhai")
+		.args(["build", "--release", "--jobs=4", "linux"])
+		.output()
+		.expect("Failed to execute command");
+
+	assert!(output.status.success());
+
+	let stdout = String::from_utf8(output.stdout).unwrap();
+	assert_eq!(stdout, "build linux release=true jobs=4\n");
+
+	let output = Command::new("./target/debug/riji")
+		.env("RIJI_SCRIPT", "tests/test_args.rhai")
+		.arg("build")
+		.output()
+		.expect("Failed to execute command");
+
+	assert!(!output.status.success());
+
+	let stderr = String::from_utf8(output.stderr).unwrap();
+	assert!(stderr.contains("riji build <target> [--options]"));
+}
+
+#[test]
+fn test_error() {
+	let output = Command::new("./target/debug/riji")
+		.env("RIJI_SCRIPT", "tests/test_error.rhai")
+		.arg("build")
+		.output()
+		.expect("Failed to execute command");
+
+	assert!(!output.status.success());
+
+	let stderr = String::from_utf8(output.stderr).unwrap();
+	assert!(stderr.contains("Variable not found: undefined_var"));
+	assert!(stderr.contains("./test_error.rhai:2:14"));
+	assert!(stderr.contains("let y = x + undefined_var;"));
+	assert!(stderr.contains("in call to function 'inner' at ./test_error.rhai:6:2"));
+}
+
+#[test]
+fn test_error_native() {
+	let output = Command::new("./target/debug/riji")
+		.env("RIJI_SCRIPT", "tests/test_error.rhai")
+		.arg("native")
+		.output()
+		.expect("Failed to execute command");
+
+	assert!(!output.status.success());
+
+	let stderr = String::from_utf8(output.stderr).unwrap();
+	assert!(stderr.contains("could not read file missing_file.txt"));
+	assert!(stderr.contains("./test_error.rhai:14:2"));
+	assert!(stderr.contains("read_missing();"));
+}
+
+#[test]
+fn test_discover_root() {
+	let root = Path::new(env!("CARGO_TARGET_TMPDIR")).join("discover");
+	let nested = root.join("nested/deep");
+	fs::create_dir_all(&nested).unwrap();
+	fs::write(
+		root.join("riji.rhai"),
+		"fn root() { print(riji_root()); }"
+	).unwrap();
+
+	let output = Command::new(env!("CARGO_BIN_EXE_riji"))
+		.env_remove("RIJI_SCRIPT")
+		.current_dir(&nested)
+		.arg("root")
+		.output()
+		.expect("Failed to execute command");
+
+	assert!(output.status.success());
+
+	let stdout = String::from_utf8(output.stdout).unwrap();
+	let root = dunce::canonicalize(&root).unwrap();
+	assert_eq!(stdout.trim_end(), root.to_str().unwrap());
+}
+
+#[test]
+fn test_import() {
+	let output = Command::new("./target/debug/riji")
+		.env("RIJI_SCRIPT", "tests/test_import.rhai")
+		.env("RIJI_LIB", "shared")
+		.arg("greet")
+		.output()
+		.expect("Failed to execute command");
+
+	assert!(output.status.success());
+
+	let stdout = String::from_utf8(output.stdout).unwrap();
+	assert_eq!(stdout, "hello riji\ncommon\n");
+}
+
+#[test]
+fn test_tasks() {
+	let output = Command::new("./target/debug/riji")
+		.env("RIJI_SCRIPT", "tests/test_tasks.rhai")
+		.arg("test")
+		.output()
+		.expect("Failed to execute command");
+
+	assert!(output.status.success());
+
+	let stdout = String::from_utf8(output.stdout).unwrap();
+	assert_eq!(stdout, "clone\npatch\nbuild\ntest\n");
+
+	let stderr = String::from_utf8(output.stderr).unwrap();
+	assert!(stderr.contains("plan: clone -> patch -> build -> test"));
+
+	let output = Command::new("./target/debug/riji")
+		.env("RIJI_SCRIPT", "tests/test_tasks.rhai")
+		.arg("loop_a")
+		.output()
+		.expect("Failed to execute command");
+
+	assert!(!output.status.success());
+
+	let stderr = String::from_utf8(output.stderr).unwrap();
+	assert!(stderr.contains("loop_a -> loop_b -> loop_a"));
+}
+
+#[test]
+fn test_multiple_commands() {
+	let output = Command::new("./target/debug/riji")
+		.env("RIJI_SCRIPT", "tests/test_multi.rhai")
+		.args(["clone", "build", "linux"])
+		.output()
+		.expect("Failed to execute command");
+
+	assert!(output.status.success());
+
+	let stdout = String::from_utf8(output.stdout).unwrap();
+	assert_eq!(stdout, "clone 1\nbuild linux 2\n");
+
+	let output = Command::new("./target/debug/riji")
+		.env("RIJI_SCRIPT", "tests/test_multi.rhai")
+		.args(["clone", "fail", "build", "--", "clone"])
+		.output()
+		.expect("Failed to execute command");
+
+	assert!(!output.status.success());
+
+	let stdout = String::from_utf8(output.stdout).unwrap();
+	assert_eq!(stdout, "clone 1\n");
+}
+
+#[test]
+fn test_dry_run() {
+	// the flag is accepted before and after the command
+	for args in [["--dry-run", "run"], ["run", "--dry-run"]] {
+		let output = Command::new("./target/debug/riji")
+			.env("RIJI_SCRIPT", "tests/test_dry_run.rhai")
+			.args(args)
+			.output()
+			.expect("Failed to execute command");
+
+		assert!(output.status.success());
+
+		let stdout = String::from_utf8(output.stdout).unwrap();
+		assert_eq!(stdout, "out: \"\" dry: true\n");
+
+		let stderr = String::from_utf8(output.stderr).unwrap();
+		assert!(stderr.contains("write \"dry_run_output.txt\""));
+		assert!(stderr.contains("executing \"false\""));
+		assert!(!Path::new("tests/dry_run_output.txt").exists());
+	}
+}
+
+#[test]
+fn test_dry_run_after_separator() {
+	let output = Command::new("./target/debug/riji")
+		.env("RIJI_SCRIPT", "tests/test_dry_run.rhai")
+		.args(["flags", "--", "--dry-run"])
+		.output()
+		.expect("Failed to execute command");
+
+	assert!(output.status.success());
+
+	let stdout = String::from_utf8(output.stdout).unwrap();
+	assert_eq!(stdout, "#{\"dry-run\": true} false\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cmd_run() {
+	let output = Command::new("./target/debug/riji")
+		.env("RIJI_SCRIPT", "tests/test_cmd.rhai")
+		.arg("capture")
+		.output()
+		.expect("Failed to execute command");
+
+	assert!(output.status.success());
+
+	let stdout = String::from_utf8(output.stdout).unwrap();
+	assert_eq!(stdout, "out err 3 false\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cmd_pipeline() {
+	let output = Command::new("./target/debug/riji")
+		.env("RIJI_SCRIPT", "tests/test_cmd.rhai")
+		.arg("pipeline")
+		.output()
+		.expect("Failed to execute command");
+
+	assert!(output.status.success());
+
+	let stdout = String::from_utf8(output.stdout).unwrap();
+	assert_eq!(stdout, "a\nb\nc\n\n[2, 0] 0 false\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cmd_timeout() {
+	let start = std::time::Instant::now();
+	let output = Command::new("./target/debug/riji")
+		.env("RIJI_SCRIPT", "tests/test_cmd.rhai")
+		.arg("timeout")
+		.output()
+		.expect("Failed to execute command");
+
+	assert!(output.status.success());
+	assert!(start.elapsed().as_secs() < 4);
+
+	let stdout = String::from_utf8(output.stdout).unwrap();
+	assert_eq!(stdout, "true\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cmd_parallel() {
+	let output = Command::new("./target/debug/riji")
+		.env("RIJI_SCRIPT", "tests/test_cmd.rhai")
+		.arg("parallel")
+		.output()
+		.expect("Failed to execute command");
+
+	assert!(output.status.success());
+
+	let stdout = String::from_utf8(output.stdout).unwrap();
+	assert!(stdout.contains("[first] a\n"));
+	assert!(stdout.contains("first true false\n"));
+	assert!(stdout.contains("second false false\n"));
+	// either killed or never started
+	assert!(stdout.contains("sleep false"));
+}
+
+/// Runs `cmd` with a stdin pipe which stays open until riji exits.
+fn run_with_open_stdin(cmd: &str) -> Output {
+	let mut child = Command::new("./target/debug/riji")
+		.env("RIJI_SCRIPT", "tests/test_cmd.rhai")
+		.arg(cmd)
+		.stdin(Stdio::piped())
+		.stdout(Stdio::piped())
+		.stderr(Stdio::piped())
+		.spawn()
+		.expect("Failed to execute command");
+
+	let _stdin = child.stdin.take();
+	child.wait_with_output().unwrap()
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cmd_parallel_stdin() {
+	let output = run_with_open_stdin("parallel_stdin");
+
+	assert!(output.status.success());
+
+	let stdout = String::from_utf8(output.stdout).unwrap();
+	assert_eq!(stdout, "true\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cmd_pipe_bad_dir() {
+	let file = Path::new(env!("CARGO_TARGET_TMPDIR")).join("bad_dir_started");
+	let _ = fs::remove_file(&file);
+
+	let output = Command::new("./target/debug/riji")
+		.env("RIJI_SCRIPT", "tests/test_cmd.rhai")
+		.env("STARTED_FILE", &file)
+		.arg("bad_dir")
+		.output()
+		.expect("Failed to execute command");
+
+	assert!(output.status.success());
+
+	let stdout = String::from_utf8(output.stdout).unwrap();
+	assert!(stdout.starts_with("invalid directory \"does-not-exist\" for \"cat\""));
+	// the first stage was never started
+	assert!(!file.exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cmd_ctrlc_terminates() {
+	let file = Path::new(env!("CARGO_TARGET_TMPDIR")).join("ctrlc_term");
+	let _ = fs::remove_file(&file);
+
+	let mut child = Command::new("./target/debug/riji")
+		.env("RIJI_SCRIPT", "tests/test_cmd.rhai")
+		.env("TERM_FILE", &file)
+		.arg("interrupted")
+		.stdout(Stdio::null())
+		.stderr(Stdio::null())
+		.spawn()
+		.expect("Failed to execute command");
+
+	// wait until the command runs
+	for _ in 0..100 {
+		if fs::read_to_string(&file).map(|s| s == "started\n").unwrap_or(false) {
+			break
+		}
+		std::thread::sleep(std::time::Duration::from_millis(50));
+	}
+
+	Command::new("kill")
+		.args(["-INT", &child.id().to_string()])
+		.status()
+		.unwrap();
+	let status = child.wait().unwrap();
+
+	assert!(!status.success());
+	// the command received SIGTERM instead of being killed
+	assert_eq!(fs::read_to_string(&file).unwrap(), "terminated\n");
+}
+
+#[test]
+fn test_cmd_missing_program() {
+	let output = Command::new("./target/debug/riji")
+		.env("RIJI_SCRIPT", "tests/test_cmd.rhai")
+		.arg("missing")
+		.output()
+		.expect("Failed to execute command");
+
+	assert!(output.status.success());
+
+	let stdout = String::from_utf8(output.stdout).unwrap();
+	assert!(stdout.starts_with("failed to execute \"riji-missing-program\""));
+	assert!(stdout.contains("hint: is \"riji-missing-program\" installed"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cmd_sh() {
+	let output = Command::new("./target/debug/riji")
+		.env("RIJI_SCRIPT", "tests/test_cmd.rhai")
+		.arg("shell")
+		.output()
+		.expect("Failed to execute command");
+
+	assert!(output.status.success());
+
+	let stdout = String::from_utf8(output.stdout).unwrap();
+	assert_eq!(stdout, "a|b c|d e|\n'my crate'\n");
+
+	let stderr = String::from_utf8(output.stderr).unwrap();
+	assert!(stderr.contains("executing \"printf '%s|' a 'b c' 'd e'\""));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cmd_stream() {
+	let output = Command::new("./target/debug/riji")
+		.env("RIJI_SCRIPT", "tests/test_cmd.rhai")
+		.arg("lines")
+		.output()
+		.expect("Failed to execute command");
+
+	assert!(output.status.success());
+
+	let stdout = String::from_utf8(output.stdout).unwrap();
+	assert!(stdout.contains("stdout: a\n"));
+	assert!(stdout.contains("stderr: b\n"));
+	assert!(stdout.ends_with("stdout: stop\ntrue false\n"));
+	assert!(!stdout.contains("never"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cmd_stream_stdin() {
+	let output = run_with_open_stdin("lines_stdin");
+
+	assert!(output.status.success());
+
+	let stdout = String::from_utf8(output.stdout).unwrap();
+	assert_eq!(stdout, "true\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cmd_spawn() {
+	let output = Command::new("./target/debug/riji")
+		.env("RIJI_SCRIPT", "tests/test_cmd.rhai")
+		.arg("background")
+		.output()
+		.expect("Failed to execute command");
+
+	assert!(output.status.success());
+
+	let stdout = String::from_utf8(output.stdout).unwrap();
+	assert_eq!(stdout, "true true\nfalse 0\nhi 2 false\n");
+
+	let stderr = String::from_utf8(output.stderr).unwrap();
+	assert!(stderr.contains("killing \"sh -c 'sleep 5; echo never'\""));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cmd_spawn_stdin() {
+	let output = run_with_open_stdin("background_stdin");
+
+	assert!(output.status.success());
+
+	let stdout = String::from_utf8(output.stdout).unwrap();
+	assert_eq!(stdout, "true\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cmd_retry() {
+	let file = Path::new(env!("CARGO_TARGET_TMPDIR")).join("retry_count");
+	let _ = fs::remove_file(&file);
+
+	let output = Command::new("./target/debug/riji")
+		.env("RIJI_SCRIPT", "tests/test_cmd.rhai")
+		.env("RETRY_FILE", &file)
+		.arg("flaky")
+		.output()
+		.expect("Failed to execute command");
+
+	assert!(output.status.success());
+
+	let stdout = String::from_utf8(output.stdout).unwrap();
+	assert_eq!(stdout, "3\nfalse\n");
+
+	let stderr = String::from_utf8(output.stderr).unwrap();
+	assert!(stderr.contains("failed (attempt 1/4), retrying in 10ms"));
+	assert!(stderr.contains("failed (attempt 2/4), retrying in 20ms"));
+	assert!(!stderr.contains("attempt 3/4"));
+	assert!(stderr.contains("failed (attempt 1/2), retrying in 10ms"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_env() {
+	let output = Command::new("./target/debug/riji")
+		.env("RIJI_SCRIPT", "tests/test_env.rhai")
+		.env("RIJI_EXISTING", "from env")
+		.env_remove("RIJI_GLOBAL")
+		.arg("vars")
+		.output()
+		.expect("Failed to execute command");
+
+	assert!(output.status.success());
+
+	let stdout = String::from_utf8(output.stdout).unwrap();
+	assert_eq!(
+		stdout,
+		"plain value\nline\none \"quoted\"\nno $expansion # here\nfrom env\n\
+		global||unset\nunset\n"
+	);
+}
+
+#[test]
+fn test_env_nul() {
+	let output = Command::new("./target/debug/riji")
+		.env("RIJI_SCRIPT", "tests/test_env.rhai")
+		.arg("nul")
+		.output()
+		.expect("Failed to execute command");
+
+	assert!(output.status.success());
+
+	let stdout = String::from_utf8(output.stdout).unwrap();
+	assert_eq!(
+		stdout,
+		"invalid value for environment variable \"RIJI_NUL\"\n\
+		invalid value for environment variable \"RIJI_NUL\"\n"
+	);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_fs_copy() {
+	use std::os::unix::fs::{symlink, PermissionsExt};
+
+	let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("fs_copy");
+	let _ = fs::remove_dir_all(&dir);
+	let src = dir.join("src");
+	fs::create_dir_all(src.join("sub")).unwrap();
+	fs::create_dir_all(src.join("skip")).unwrap();
+	fs::write(src.join("a.txt"), "a").unwrap();
+	fs::set_permissions(src.join("a.txt"), fs::Permissions::from_mode(0o755))
+		.unwrap();
+	fs::write(src.join("sub/b.rs"), "b").unwrap();
+	fs::write(src.join("skip/c.txt"), "c").unwrap();
+	symlink("a.txt", src.join("link")).unwrap();
+
+	let output = Command::new("./target/debug/riji")
+		.env("RIJI_SCRIPT", "tests/test_fs.rhai")
+		.env("FS_DIR", &dir)
+		.arg("copy_tree")
+		.output()
+		.expect("Failed to execute command");
+
+	assert!(output.status.success());
+
+	let stdout = String::from_utf8(output.stdout).unwrap();
+	assert!(stdout.contains("already exists"));
+	assert!(stdout.contains("into itself"));
+	assert_eq!(stdout.matches("are the same file").count(), 2);
+	assert_eq!(fs::read_to_string(src.join("a.txt")).unwrap(), "a");
+	assert!(!src.join("sub/src").exists());
+
+	let all = dir.join("all");
+	assert_eq!(fs::read_to_string(all.join("a.txt")).unwrap(), "changed");
+	assert_eq!(fs::read_to_string(all.join("sub/b.rs")).unwrap(), "b");
+	assert_eq!(fs::read_to_string(all.join("skip/c.txt")).unwrap(), "c");
+	let mode = fs::metadata(all.join("a.txt")).unwrap().permissions().mode();
+	assert_eq!(mode & 0o777, 0o755);
+	assert_eq!(fs::read_link(all.join("link")).unwrap(), Path::new("a.txt"));
+
+	let filtered = dir.join("filtered");
+	assert!(filtered.join("a.txt").is_file());
+	assert!(filtered.join("link").is_symlink());
+	assert!(!filtered.join("sub").exists());
+	assert!(!filtered.join("skip").exists());
+}
+
+#[test]
+fn test_fs_walk() {
+	let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("fs_walk");
+	let _ = fs::remove_dir_all(&dir);
+	fs::create_dir_all(dir.join("sub/deep")).unwrap();
+	fs::create_dir_all(dir.join("target")).unwrap();
+	fs::write(dir.join("a.rs"), "").unwrap();
+	fs::write(dir.join(".gitignore"), "target/\n").unwrap();
+	fs::write(dir.join("sub/b.rs"), "").unwrap();
+	fs::write(dir.join("sub/deep/c.rs"), "").unwrap();
+	fs::write(dir.join("target/x.rs"), "").unwrap();
+
+	let dir = dir.to_str().unwrap().replace('\\', "/");
+	let output = Command::new("./target/debug/riji")
+		.env("RIJI_SCRIPT", "tests/test_fs.rhai")
+		.env("FS_DIR", &dir)
+		.arg("tree")
+		.output()
+		.expect("Failed to execute command");
+
+	assert!(output.status.success());
+
+	let stdout = String::from_utf8(output.stdout).unwrap()
+		.replace(&dir, "");
+	assert_eq!(stdout, "\
+		a.rs\nsub\nsub/b.rs\nsub/deep\nsub/deep/c.rs\ntarget\ntarget/x.rs\n-\n\
+		.gitignore\na.rs\nsub/b.rs\nsub/deep/c.rs\n-\n\
+		sub\ntarget\n-\n\
+		a.rs\nsub/b.rs\n-\n\
+		/a.rs\n/sub/b.rs\n/sub/deep/c.rs\n/target/x.rs\n-\n\
+		/sub/b.rs\n\
+	");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_fs_meta() {
+	let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("fs_meta");
+	let _ = fs::remove_dir_all(&dir);
+	fs::create_dir_all(&dir).unwrap();
+
+	let output = Command::new("./target/debug/riji")
+		.env("RIJI_SCRIPT", "tests/test_fs.rhai")
+		.env("FS_DIR", &dir)
+		.arg("meta")
+		.output()
+		.expect("Failed to execute command");
+
+	assert!(output.status.success());
+
+	let stdout = String::from_utf8(output.stdout).unwrap();
+	assert_eq!(
+		stdout,
+		"file 7 true true\ntrue\nfalse\nrun.sh\nsymlink\ndir\n"
+	);
+
+	let stderr = String::from_utf8(output.stderr).unwrap();
+	assert!(stderr.contains("chmod 750"));
+}
+
+#[test]
+fn test_fs_bytes() {
+	let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("fs_bytes");
+	let _ = fs::remove_dir_all(&dir);
+	fs::create_dir_all(&dir).unwrap();
+
+	let output = Command::new("./target/debug/riji")
+		.env("RIJI_SCRIPT", "tests/test_fs.rhai")
+		.env("FS_DIR", &dir)
+		.arg("bytes")
+		.output()
+		.expect("Failed to execute command");
+
+	assert!(output.status.success());
+
+	let stdout = String::from_utf8(output.stdout).unwrap();
+	assert_eq!(
+		stdout,
+		"5 255 99\ntrue\ntrue\n\
+		ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad\n\
+		true\nfalse\n128\n"
+	);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_fs_hash_symlinks() {
+	let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("fs_hash_links");
+	let _ = fs::remove_dir_all(&dir);
+	fs::create_dir_all(&dir).unwrap();
+
+	let output = Command::new("./target/debug/riji")
+		.env("RIJI_SCRIPT", "tests/test_fs.rhai")
+		.env("FS_DIR", &dir)
+		.arg("hash_links")
+		.output()
+		.expect("Failed to execute command");
+
+	assert!(output.status.success());
+
+	let stdout = String::from_utf8(output.stdout).unwrap();
+	assert_eq!(stdout, "false\n");
+}
+
+#[test]
+fn test_fs_atomic() {
+	let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("fs_atomic");
+	let _ = fs::remove_dir_all(&dir);
+	fs::create_dir_all(&dir).unwrap();
+	fs::write(dir.join("a.txt"), "old").unwrap();
+
+	let output = Command::new("./target/debug/riji")
+		.env("RIJI_SCRIPT", "tests/test_fs.rhai")
+		.env("FS_DIR", &dir)
+		.arg("atomic")
+		.output()
+		.expect("Failed to execute command");
+
+	assert!(output.status.success());
+
+	// no temporary files are left
+	let stdout = String::from_utf8(output.stdout).unwrap();
+	assert_eq!(stdout, "new\nold\nreplaced\n2\n");
+}
+
+#[test]
+fn test_fs_transaction() {
+	let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("fs_transaction");
+	let _ = fs::remove_dir_all(&dir);
+	fs::create_dir_all(dir.join("sub")).unwrap();
+	fs::write(dir.join("a.txt"), "a").unwrap();
+	fs::write(dir.join("b.txt"), "b").unwrap();
+	fs::write(dir.join("c.txt"), "c").unwrap();
+	fs::write(dir.join("sub/s.txt"), "s").unwrap();
+	fs::create_dir_all(dir.join("tree/empty")).unwrap();
+	fs::write(dir.join("tree/t.txt"), "t").unwrap();
+
+	let output = Command::new("./target/debug/riji")
+		.env("RIJI_SCRIPT", "tests/test_fs.rhai")
+		.env("FS_DIR", &dir)
+		.arg("failing")
+		.output()
+		.expect("Failed to execute command");
+
+	assert!(!output.status.success());
+	let stderr = String::from_utf8(output.stderr).unwrap();
+	assert!(stderr.contains("task failed"));
+	assert!(stderr.contains("rolling back"));
+
+	assert_eq!(fs::read_to_string(dir.join("a.txt")).unwrap(), "a");
+	assert_eq!(fs::read_to_string(dir.join("b.txt")).unwrap(), "b");
+	assert_eq!(fs::read_to_string(dir.join("c.txt")).unwrap(), "c");
+	assert_eq!(fs::read_to_string(dir.join("sub/s.txt")).unwrap(), "s");
+	assert!(!dir.join("created.txt").exists());
+	assert!(!dir.join("d.txt").exists());
+	assert!(!dir.join("new").exists());
+	assert!(!dir.join("copied").exists());
+	assert!(dir.join("tree/empty").is_dir());
+
+	let output = Command::new("./target/debug/riji")
+		.env("RIJI_SCRIPT", "tests/test_fs.rhai")
+		.env("FS_DIR", &dir)
+		.arg("committed")
+		.output()
+		.expect("Failed to execute command");
+
+	assert!(output.status.success());
+	assert_eq!(fs::read_to_string(dir.join("a.txt")).unwrap(), "committed");
+}
+
+#[test]
+fn test_fs_temp() {
+	let output = Command::new("./target/debug/riji")
+		.env("RIJI_SCRIPT", "tests/test_fs.rhai")
+		.arg("temps")
+		.output()
+		.expect("Failed to execute command");
+
+	assert!(output.status.success());
+
+	let stdout = String::from_utf8(output.stdout).unwrap();
+	let lines: Vec<_> = stdout.lines().collect();
+	assert_eq!(lines[2], "true");
+	assert!(!Path::new(lines[0]).exists());
+	assert!(!Path::new(lines[1]).exists());
+
+	let output = Command::new("./target/debug/riji")
+		.env("RIJI_SCRIPT", "tests/test_fs.rhai")
+		.args(["--keep-temp", "temps"])
+		.output()
+		.expect("Failed to execute command");
+
+	assert!(output.status.success());
+
+	let stdout = String::from_utf8(output.stdout).unwrap();
+	let lines: Vec<_> = stdout.lines().collect();
+	assert!(Path::new(lines[0]).join("inner.txt").is_file());
+	assert!(Path::new(lines[1]).is_file());
+	fs::remove_dir_all(lines[0]).unwrap();
+	fs::remove_file(lines[1]).unwrap();
+}
+
+#[test]
+fn test_archive() {
+	let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("archive");
+	let _ = fs::remove_dir_all(&dir);
+	let src = dir.join("src");
+	fs::create_dir_all(src.join("sub/empty")).unwrap();
+	fs::create_dir_all(src.join("target")).unwrap();
+	fs::write(src.join("a.txt"), "a").unwrap();
+	fs::write(src.join("sub/b.rs"), "b").unwrap();
+	fs::write(src.join("sub/c.txt"), "c").unwrap();
+	fs::write(src.join("target/x.txt"), "x").unwrap();
+
+	let output = Command::new("./target/debug/riji")
+		.env("RIJI_SCRIPT", "tests/test_archive.rhai")
+		.env("ARCHIVE_DIR", &dir)
+		.arg("roundtrip")
+		.output()
+		.expect("Failed to execute command");
+
+	assert!(output.status.success());
+
+	for ext in ["tar", "tar.gz", "zip"] {
+		let all = dir.join(format!("all-{}", ext));
+		assert_eq!(fs::read_to_string(all.join("a.txt")).unwrap(), "a");
+		assert_eq!(fs::read_to_string(all.join("sub/b.rs")).unwrap(), "b");
+		assert!(all.join("sub/empty").is_dir());
+		assert!(!all.join("target").exists());
+
+		let txt = dir.join(format!("txt-{}", ext));
+		assert!(txt.join("a.txt").is_file());
+		assert!(txt.join("sub/c.txt").is_file());
+		assert!(!txt.join("sub/b.rs").exists());
+		assert!(!txt.join("sub/empty").exists());
+	}
+}
+
+#[test]
+fn test_archive_inside_source() {
+	let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("archive_inside");
+	let _ = fs::remove_dir_all(&dir);
+	fs::create_dir_all(dir.join("src")).unwrap();
+	fs::write(dir.join("src/a.txt"), "a").unwrap();
+
+	let output = Command::new("./target/debug/riji")
+		.env("RIJI_SCRIPT", "tests/test_archive.rhai")
+		.env("ARCHIVE_DIR", &dir)
+		.arg("inside")
+		.output()
+		.expect("Failed to execute command");
+
+	assert!(output.status.success());
+
+	for ext in ["tar", "tar.gz", "zip"] {
+		let inside = dir.join(format!("inside-{}", ext));
+		assert!(inside.join("a.txt").is_file());
+		assert!(!inside.join(format!("out.{}", ext)).exists());
+	}
+}
+
+#[cfg(unix)]
+#[test]
+fn test_archive_unsafe() {
+	use std::os::unix::fs::symlink;
+
+	let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("archive_unsafe");
+	let _ = fs::remove_dir_all(&dir);
+	let outside = dir.join("outside");
+	fs::create_dir_all(&outside).unwrap();
+
+	// a symlink pointing outside of the destination
+	fs::create_dir_all(dir.join("abs-src")).unwrap();
+	symlink(&outside, dir.join("abs-src/link")).unwrap();
+
+	// a file written through an existing symlink in the destination
+	fs::create_dir_all(dir.join("parent-src/link")).unwrap();
+	fs::write(dir.join("parent-src/link/pwned.txt"), "pwned").unwrap();
+	for ext in ["zip", "tar", "tar.gz"] {
+		let dest = dir.join(format!("parent-dest-{}", ext));
+		fs::create_dir_all(&dest).unwrap();
+		symlink(&outside, dest.join("link")).unwrap();
+	}
+
+	let output = Command::new("./target/debug/riji")
+		.env("RIJI_SCRIPT", "tests/test_archive.rhai")
+		.env("ARCHIVE_DIR", &dir)
+		.arg("unsafe_archives")
+		.output()
+		.expect("Failed to execute command");
+
+	assert!(output.status.success());
+
+	let stdout = String::from_utf8(output.stdout).unwrap();
+	assert_eq!(stdout, "true\n".repeat(6));
+	for ext in ["zip", "tar", "tar.gz"] {
+		let link = dir.join(format!("abs-dest-{}/link", ext));
+		assert!(fs::symlink_metadata(link).is_err());
+	}
+	assert_eq!(fs::read_dir(&outside).unwrap().count(), 0);
+}